@@ -10,8 +10,12 @@ fn main() {
     let header = quickjs.join("quickjs.h");
     let header = header.to_str().unwrap();
 
-    let binding = bindgen::builder()
-        .header(header)
+    let binding = bindgen::builder().header(header);
+
+    #[cfg(feature = "libc")]
+    let binding = binding.header(quickjs.join("quickjs-libc.h").to_str().unwrap());
+
+    let binding = binding
         .allowlist_function("(__)?(JS|js)_.*")
         .allowlist_var("JS_.*")
         .allowlist_type("JS.*")
@@ -46,7 +50,8 @@ fn main() {
             .unwrap();
     }
 
-    let sources = [
+    #[allow(unused_mut)]
+    let mut sources = vec![
         "cutils.c",
         "libbf.c",
         "libregexp.c",
@@ -55,6 +60,15 @@ fn main() {
         "static-functions.c",
     ];
 
+    #[cfg(feature = "libc")]
+    {
+        sources.push("quickjs-libc.c");
+
+        if env::var("CARGO_CFG_TARGET_OS").unwrap() == "linux" {
+            println!("cargo:rustc-link-lib=dl");
+        }
+    }
+
     cc::Build::new()
         .files(sources.iter().map(|f| code_path.join(f)))
         .define("_GNU_SOURCE", None)
@@ -0,0 +1,274 @@
+use quickjs_sys as sys;
+use serde::Serialize;
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    ffi::c_void,
+    ptr,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+const HEADER: usize = 16;
+
+pub trait JsAllocator: Send {
+    /// Returns a null pointer when the allocation fails.
+    fn alloc(&self, layout: Layout) -> *mut u8;
+
+    fn dealloc(&self, ptr: *mut u8, layout: Layout);
+
+    fn realloc(&self, ptr: *mut u8, layout: Layout, size: usize) -> *mut u8 {
+        let new = match Layout::from_size_align(size, layout.align()) {
+            Ok(v) => self.alloc(v),
+            Err(_) => return ptr::null_mut(),
+        };
+
+        if !new.is_null() {
+            unsafe { ptr::copy_nonoverlapping(ptr, new, layout.size().min(size)) };
+            self.dealloc(ptr, layout);
+        }
+
+        new
+    }
+}
+
+impl JsAllocator for System {
+    fn alloc(&self, layout: Layout) -> *mut u8 {
+        unsafe { GlobalAlloc::alloc(self, layout) }
+    }
+
+    fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { GlobalAlloc::dealloc(self, ptr, layout) }
+    }
+
+    fn realloc(&self, ptr: *mut u8, layout: Layout, size: usize) -> *mut u8 {
+        unsafe { GlobalAlloc::realloc(self, ptr, layout, size) }
+    }
+}
+
+#[derive(Serialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct AllocatorStats {
+    pub allocated: usize,
+    pub peak: usize,
+    pub count: usize,
+    pub failures: usize,
+    pub quota: Option<usize>,
+}
+
+pub(crate) struct Accounting {
+    allocator: Box<dyn JsAllocator>,
+    allocated: AtomicUsize,
+    peak: AtomicUsize,
+    count: AtomicUsize,
+    failures: AtomicUsize,
+    quota: AtomicUsize,
+}
+
+impl Accounting {
+    pub(crate) fn new(allocator: Box<dyn JsAllocator>) -> Self {
+        Accounting {
+            allocator,
+            allocated: AtomicUsize::new(0),
+            peak: AtomicUsize::new(0),
+            count: AtomicUsize::new(0),
+            failures: AtomicUsize::new(0),
+            quota: AtomicUsize::new(0),
+        }
+    }
+
+    pub(crate) fn stats(&self) -> AllocatorStats {
+        AllocatorStats {
+            allocated: self.allocated.load(Ordering::Relaxed),
+            peak: self.peak.load(Ordering::Relaxed),
+            count: self.count.load(Ordering::Relaxed),
+            failures: self.failures.load(Ordering::Relaxed),
+            quota: match self.quota.load(Ordering::Relaxed) {
+                0 => None,
+                v => Some(v),
+            },
+        }
+    }
+
+    pub(crate) fn set_quota(&self, quota: Option<usize>) {
+        self.quota.store(quota.unwrap_or(0), Ordering::Relaxed);
+    }
+
+    fn reserve(&self, size: usize) -> bool {
+        let quota = self.quota.load(Ordering::Relaxed);
+        let allocated = self.allocated.fetch_add(size, Ordering::Relaxed) + size;
+
+        if quota != 0 && allocated > quota {
+            self.allocated.fetch_sub(size, Ordering::Relaxed);
+            self.failures.fetch_add(1, Ordering::Relaxed);
+            return false;
+        }
+
+        self.peak.fetch_max(allocated, Ordering::Relaxed);
+        true
+    }
+
+    fn release(&self, size: usize) {
+        self.allocated.fetch_sub(size, Ordering::Relaxed);
+    }
+}
+
+pub(crate) const FUNCTIONS: sys::JSMallocFunctions = sys::JSMallocFunctions {
+    js_malloc: Some(js_malloc),
+    js_free: Some(js_free),
+    js_realloc: Some(js_realloc),
+    js_malloc_usable_size: Some(js_malloc_usable_size),
+};
+
+fn layout(size: usize) -> Option<Layout> {
+    Layout::from_size_align(size.checked_add(HEADER)?, HEADER).ok()
+}
+
+unsafe fn header(ptr: *const c_void) -> *mut usize {
+    (ptr as *mut u8).sub(HEADER) as *mut usize
+}
+
+fn over_limit(s: &sys::JSMallocState, size: usize) -> bool {
+    // A limit of 0 means unlimited, so `malloc_limit - 1` wraps to usize::MAX.
+    s.malloc_size.saturating_add(size) > s.malloc_limit.wrapping_sub(1)
+}
+
+unsafe extern "C" fn js_malloc(s: *mut sys::JSMallocState, size: usize) -> *mut c_void {
+    let s = &mut *s;
+    let accounting = &*(s.opaque as *const Accounting);
+
+    let layout = match layout(size) {
+        Some(v) if !over_limit(s, v.size()) => v,
+        _ => return ptr::null_mut(),
+    };
+
+    if !accounting.reserve(layout.size()) {
+        return ptr::null_mut();
+    }
+
+    let base = accounting.allocator.alloc(layout);
+    if base.is_null() {
+        accounting.release(layout.size());
+        accounting.failures.fetch_add(1, Ordering::Relaxed);
+        return ptr::null_mut();
+    }
+
+    (base as *mut usize).write(size);
+    accounting.count.fetch_add(1, Ordering::Relaxed);
+    s.malloc_count += 1;
+    s.malloc_size += layout.size();
+
+    base.add(HEADER) as *mut c_void
+}
+
+unsafe extern "C" fn js_free(s: *mut sys::JSMallocState, ptr: *mut c_void) {
+    if ptr.is_null() {
+        return;
+    }
+
+    let s = &mut *s;
+    let accounting = &*(s.opaque as *const Accounting);
+
+    let base = header(ptr);
+    let layout = layout(*base).unwrap();
+    accounting.allocator.dealloc(base as *mut u8, layout);
+
+    accounting.release(layout.size());
+    accounting.count.fetch_sub(1, Ordering::Relaxed);
+    s.malloc_count -= 1;
+    s.malloc_size -= layout.size();
+}
+
+unsafe extern "C" fn js_realloc(
+    s: *mut sys::JSMallocState,
+    ptr: *mut c_void,
+    size: usize,
+) -> *mut c_void {
+    if ptr.is_null() {
+        return if size == 0 {
+            ptr::null_mut()
+        } else {
+            js_malloc(s, size)
+        };
+    }
+
+    if size == 0 {
+        js_free(s, ptr);
+        return ptr::null_mut();
+    }
+
+    let s = &mut *s;
+    let accounting = &*(s.opaque as *const Accounting);
+
+    let base = header(ptr);
+    let old = layout(*base).unwrap();
+    let new = match layout(size) {
+        Some(v) if !over_limit(s, v.size().saturating_sub(old.size())) => v,
+        _ => return ptr::null_mut(),
+    };
+
+    let grow = new.size().saturating_sub(old.size());
+    if grow > 0 && !accounting.reserve(grow) {
+        return ptr::null_mut();
+    }
+
+    let base = accounting
+        .allocator
+        .realloc(base as *mut u8, old, new.size());
+    if base.is_null() {
+        accounting.release(grow);
+        accounting.failures.fetch_add(1, Ordering::Relaxed);
+        return ptr::null_mut();
+    }
+
+    accounting.release(old.size().saturating_sub(new.size()));
+    (base as *mut usize).write(size);
+    s.malloc_size = s.malloc_size + new.size() - old.size();
+
+    base.add(HEADER) as *mut c_void
+}
+
+unsafe extern "C" fn js_malloc_usable_size(ptr: *const c_void) -> usize {
+    if ptr.is_null() {
+        0
+    } else {
+        *header(ptr)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{context::Context, error::QuickError, runtime::Runtime};
+
+    #[test]
+    fn reserve_fails_over_quota() {
+        let accounting = Accounting::new(Box::new(System));
+        accounting.set_quota(Some(100));
+
+        assert!(accounting.reserve(60));
+        assert!(!accounting.reserve(60));
+        accounting.release(60);
+        assert!(accounting.reserve(100));
+
+        let stats = accounting.stats();
+        assert_eq!(stats.allocated, 100);
+        assert_eq!(stats.peak, 100);
+        assert_eq!(stats.failures, 1);
+        assert_eq!(stats.quota, Some(100));
+    }
+
+    #[test]
+    fn runtime_reports_out_of_memory_at_quota() {
+        let runtime = Runtime::with_allocator(System).unwrap();
+        let context = Context::from(&runtime);
+
+        let used = runtime.allocator_stats().unwrap().allocated;
+        runtime.set_allocator_quota(Some(used + (1 << 20)));
+
+        let result = context.eval_global("new Array(1 << 22).fill(0).length", "<test>");
+        assert!(matches!(result, Err(QuickError::OutOfMemory)));
+        assert!(runtime.allocator_stats().unwrap().failures > 0);
+
+        runtime.set_allocator_quota(None);
+        let value = context.eval_global("1 + 1", "<test>").unwrap();
+        assert_eq!(value.to_i32().unwrap(), 2);
+    }
+}
@@ -0,0 +1,89 @@
+use crate::error::QuickError;
+use quickjs_sys as sys;
+use std::ffi::CStr;
+
+const MAGIC: &[u8; 4] = b"QRBC";
+const HEADER_LEN: usize = MAGIC.len() + 8;
+
+pub(crate) fn hash(bytes: &[u8], seed: u64) -> u64 {
+    bytes
+        .iter()
+        .fold(seed ^ 0xcbf2_9ce4_8422_2325, |hash, byte| {
+            (hash ^ *byte as u64).wrapping_mul(0x0100_0000_01b3)
+        })
+}
+
+/// Identifies the engine build that produced a bytecode blob. Bytecode is
+/// only valid for the exact QuickJS version, word size and endianness.
+pub fn fingerprint() -> u64 {
+    let version = unsafe { CStr::from_ptr(sys::JS_GetVersion()) };
+
+    let fingerprint = hash(version.to_bytes(), 0);
+    let fingerprint = hash(env!("CARGO_PKG_VERSION").as_bytes(), fingerprint);
+    hash(
+        &[
+            cfg!(target_endian = "big") as u8,
+            (usize::BITS / 8) as u8,
+            sys::JS_TAG_MODULE as u8,
+        ],
+        fingerprint,
+    )
+}
+
+pub(crate) fn encode(bytecode: &[u8]) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(HEADER_LEN + bytecode.len());
+    buf.extend_from_slice(MAGIC);
+    buf.extend_from_slice(&fingerprint().to_le_bytes());
+    buf.extend_from_slice(bytecode);
+    buf
+}
+
+pub fn is_bytecode(buf: &[u8]) -> bool {
+    buf.len() >= HEADER_LEN && &buf[..MAGIC.len()] == MAGIC
+}
+
+pub(crate) fn decode(buf: &[u8]) -> Result<&[u8], QuickError> {
+    if !is_bytecode(buf) {
+        return Err(QuickError::BytecodeError(String::from("invalid header")));
+    }
+
+    let mut fingerprint = [0; 8];
+    fingerprint.copy_from_slice(&buf[MAGIC.len()..HEADER_LEN]);
+
+    if u64::from_le_bytes(fingerprint) != self::fingerprint() {
+        return Err(QuickError::BytecodeError(String::from("stale fingerprint")));
+    }
+
+    Ok(&buf[HEADER_LEN..])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decode_returns_encoded_bytecode() {
+        let buf = encode(b"bytecode");
+
+        assert!(is_bytecode(&buf));
+        assert_eq!(decode(&buf).unwrap(), b"bytecode");
+    }
+
+    #[test]
+    fn decode_rejects_bad_headers() {
+        assert!(decode(b"").is_err());
+        assert!(decode(MAGIC).is_err());
+
+        let mut buf = encode(b"bytecode");
+        buf[0] = b'X';
+        assert!(decode(&buf).is_err());
+    }
+
+    #[test]
+    fn decode_rejects_stale_fingerprints() {
+        let mut buf = encode(b"bytecode");
+        buf[MAGIC.len()] ^= 0xff;
+
+        assert!(matches!(decode(&buf), Err(QuickError::BytecodeError(_))));
+    }
+}
@@ -0,0 +1,167 @@
+use crate::bytecode;
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    process,
+    sync::atomic::{AtomicU64, Ordering},
+    time::UNIX_EPOCH,
+};
+
+static WRITES: AtomicU64 = AtomicU64::new(0);
+
+pub struct BytecodeCache {
+    dir: PathBuf,
+}
+
+impl BytecodeCache {
+    pub fn new(dir: impl Into<PathBuf>) -> io::Result<Self> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)?;
+
+        Ok(BytecodeCache { dir })
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub fn get(&self, path: impl AsRef<Path>, source: impl AsRef<str>) -> Option<Vec<u8>> {
+        let path = path.as_ref();
+
+        let mut buf = fs::read(self.entry(path)).ok()?;
+        if buf.len() < 8 {
+            return None;
+        }
+
+        let mut key = [0; 8];
+        key.copy_from_slice(&buf[..8]);
+
+        if u64::from_le_bytes(key) == Self::key(path, source.as_ref()) {
+            Some(buf.split_off(8))
+        } else {
+            None
+        }
+    }
+
+    pub fn put(
+        &self,
+        path: impl AsRef<Path>,
+        source: impl AsRef<str>,
+        bytecode: &[u8],
+    ) -> io::Result<()> {
+        let path = path.as_ref();
+        let entry = self.entry(path);
+
+        let mut buf = Vec::with_capacity(8 + bytecode.len());
+        buf.extend_from_slice(&Self::key(path, source.as_ref()).to_le_bytes());
+        buf.extend_from_slice(bytecode);
+
+        // Unique per write so threads sharing the directory never write to the
+        // same temp file.
+        let write = WRITES.fetch_add(1, Ordering::Relaxed);
+        let temp = entry.with_extension(format!("{}.{write}.tmp", process::id()));
+        fs::write(&temp, buf)?;
+        fs::rename(temp, entry)
+    }
+
+    pub fn remove(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        match fs::remove_file(self.entry(path.as_ref())) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+
+    fn entry(&self, path: &Path) -> PathBuf {
+        let path = path.to_string_lossy();
+        let name = bytecode::hash(path.as_bytes(), 0);
+
+        self.dir.join(format!("{name:016x}.qbc"))
+    }
+
+    fn key(path: &Path, source: &str) -> u64 {
+        let mtime = fs::metadata(path)
+            .and_then(|v| v.modified())
+            .ok()
+            .and_then(|v| v.duration_since(UNIX_EPOCH).ok())
+            .map(|v| v.as_nanos() as u64)
+            .unwrap_or_default();
+
+        let key = bytecode::hash(path.to_string_lossy().as_bytes(), 0);
+        let key = bytecode::hash(&mtime.to_le_bytes(), key);
+        bytecode::hash(source.as_bytes(), key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cache(name: &str) -> BytecodeCache {
+        let dir = std::env::temp_dir().join(format!("quick-rs-{name}-{}", std::process::id()));
+        BytecodeCache::new(dir).unwrap()
+    }
+
+    #[test]
+    fn key_depends_on_path_and_source() {
+        let a = Path::new("/missing/a.js");
+        let b = Path::new("/missing/b.js");
+
+        assert_eq!(BytecodeCache::key(a, "1"), BytecodeCache::key(a, "1"));
+        assert_ne!(BytecodeCache::key(a, "1"), BytecodeCache::key(a, "2"));
+        assert_ne!(BytecodeCache::key(a, "1"), BytecodeCache::key(b, "1"));
+    }
+
+    #[test]
+    fn get_misses_when_source_changes() {
+        let cache = cache("source");
+        let path = Path::new("/missing/main.js");
+
+        cache.put(path, "1", b"bytecode").unwrap();
+        assert_eq!(cache.get(path, "1").as_deref(), Some(&b"bytecode"[..]));
+        assert_eq!(cache.get(path, "2"), None);
+
+        cache.remove(path).unwrap();
+        assert_eq!(cache.get(path, "1"), None);
+
+        fs::remove_dir_all(cache.dir()).unwrap();
+    }
+
+    #[test]
+    fn get_misses_when_file_is_modified() {
+        let cache = cache("mtime");
+        let path = cache.dir().join("main.js");
+
+        fs::write(&path, "1").unwrap();
+        cache.put(&path, "1", b"bytecode").unwrap();
+        assert!(cache.get(&path, "1").is_some());
+
+        let file = fs::File::options().write(true).open(&path).unwrap();
+        file.set_modified(UNIX_EPOCH).unwrap();
+        assert_eq!(cache.get(&path, "1"), None);
+
+        fs::remove_dir_all(cache.dir()).unwrap();
+    }
+
+    #[test]
+    fn concurrent_puts_leave_a_complete_entry() {
+        let cache = std::sync::Arc::new(cache("concurrent"));
+        let path = Path::new("/missing/main.js");
+        let bytecode = vec![7; 1 << 16];
+
+        let threads: Vec<_> = (0..8)
+            .map(|_| {
+                let cache = cache.clone();
+                let bytecode = bytecode.clone();
+                std::thread::spawn(move || cache.put(path, "1", &bytecode).unwrap())
+            })
+            .collect();
+
+        for thread in threads {
+            thread.join().unwrap();
+        }
+
+        assert_eq!(cache.get(path, "1"), Some(bytecode));
+
+        fs::remove_dir_all(cache.dir()).unwrap();
+    }
+}
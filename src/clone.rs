@@ -0,0 +1,325 @@
+use crate::{context::Context, error::QuickError, function, value::JSValueRef};
+use quickjs_sys as sys;
+use std::mem::ManuallyDrop;
+
+const WRITE_FLAGS: i32 = sys::JS_WRITE_OBJ_REFERENCE as i32;
+const READ_FLAGS: i32 = sys::JS_READ_OBJ_REFERENCE as i32;
+
+// Flattens the value graph into `[root, nodes]`, where every object becomes a
+// tagged node and references are node indexes. Binary data is left for
+// JS_WriteObject, which keeps buffers shared between views. Built-ins are
+// captured once so later changes to globals cannot affect cloning, and `Map`
+// is only used when the context has it.
+const ENCODE: &str = r#"
+(function () {
+    const { keys } = Object;
+    const toString = Object.prototype.toString;
+    const call = Function.prototype.call.bind(Function.prototype.call);
+    const NativeMap = typeof Map === "function" ? Map : null;
+    const mapEntries = NativeMap && NativeMap.prototype.entries;
+    const setValues = typeof Set === "function" ? Set.prototype.values : null;
+    const getTime = typeof Date === "function" ? Date.prototype.getTime : null;
+    const isView = typeof ArrayBuffer === "function" ? ArrayBuffer.isView : () => false;
+
+    const tagOf = (v) => call(toString, v).slice(8, -1);
+
+    return function (value, ...transfer) {
+        const nodes = [];
+        const seen = NativeMap ? new NativeMap() : null;
+        const objects = [];
+
+        const lookup = (v) => {
+            if (seen) {
+                return seen.get(v);
+            }
+            const index = objects.indexOf(v);
+            return index < 0 ? undefined : index;
+        };
+
+        const remember = (v, index) => {
+            if (seen) {
+                seen.set(v, index);
+            } else {
+                objects[index] = v;
+            }
+        };
+
+        for (const buffer of transfer) {
+            if (tagOf(buffer) !== "ArrayBuffer") {
+                throw new TypeError("DataCloneError: only ArrayBuffers can be transferred");
+            }
+        }
+
+        function entries(value) {
+            const list = [];
+            for (const key of keys(value)) {
+                list.push([key, encode(value[key])]);
+            }
+            return list;
+        }
+
+        function node(value) {
+            const tag = tagOf(value);
+            const list = [];
+
+            switch (tag) {
+                case "Map":
+                    for (const [k, v] of call(mapEntries, value)) {
+                        list.push([encode(k), encode(v)]);
+                    }
+                    return [tag, list];
+                case "Set":
+                    for (const v of call(setValues, value)) {
+                        list.push(encode(v));
+                    }
+                    return [tag, list];
+                case "Date":
+                    return [tag, call(getTime, value)];
+                case "RegExp":
+                    return [tag, value.source, value.flags];
+                case "Error":
+                    return [tag, String(value.name), String(value.message), value.stack];
+                case "Boolean":
+                case "Number":
+                case "String":
+                case "BigInt":
+                    return ["Primitive", value.valueOf()];
+                case "Array":
+                    return [tag, value.length, entries(value)];
+                case "Object":
+                    return [tag, entries(value)];
+            }
+
+            if (tag === "ArrayBuffer" || isView(value)) {
+                return ["Binary", value];
+            }
+
+            throw new TypeError(`DataCloneError: ${tag} object could not be cloned`);
+        }
+
+        function encode(value) {
+            const type = typeof value;
+
+            if (type === "function" || type === "symbol") {
+                throw new TypeError(`DataCloneError: ${type} could not be cloned`);
+            }
+
+            if (value === null || type !== "object") {
+                return [0, value];
+            }
+
+            let index = lookup(value);
+            if (index === undefined) {
+                index = nodes.length;
+                remember(value, index);
+                nodes.push(null);
+                nodes[index] = node(value);
+            }
+
+            return [1, index];
+        }
+
+        return [encode(value), nodes];
+    };
+})()
+"#;
+
+// Rebuilds the graph produced by ENCODE. Properties are defined rather than
+// assigned so keys such as "__proto__" are copied as data.
+const DECODE: &str = r#"
+(function () {
+    const { defineProperty } = Object;
+    const global = globalThis;
+
+    const define = (target, key, value) =>
+        defineProperty(target, key, { value, writable: true, enumerable: true, configurable: true });
+
+    return function ([root, nodes]) {
+        const values = [];
+        const decode = ([kind, value]) => (kind === 0 ? value : values[value]);
+
+        for (const [tag, a, b, c] of nodes) {
+            switch (tag) {
+                case "Map":
+                    values.push(new global.Map());
+                    break;
+                case "Set":
+                    values.push(new global.Set());
+                    break;
+                case "Date":
+                    values.push(new global.Date(a));
+                    break;
+                case "RegExp":
+                    values.push(new global.RegExp(a, b));
+                    break;
+                case "Error": {
+                    const kind = global[a];
+                    const error =
+                        typeof kind === "function" && kind.prototype instanceof global.Error
+                            ? new kind(b)
+                            : new global.Error(b);
+                    if (c !== undefined) {
+                        defineProperty(error, "stack", { value: c, writable: true, configurable: true });
+                    }
+                    values.push(error);
+                    break;
+                }
+                case "Primitive":
+                    values.push(Object(a));
+                    break;
+                case "Binary":
+                    values.push(a);
+                    break;
+                case "Array":
+                    values.push(new Array(a));
+                    break;
+                default:
+                    values.push({});
+            }
+        }
+
+        for (let index = 0; index < nodes.length; index++) {
+            const [tag, a, b] = nodes[index];
+            const value = values[index];
+
+            switch (tag) {
+                case "Map":
+                    for (const [k, v] of a) {
+                        value.set(decode(k), decode(v));
+                    }
+                    break;
+                case "Set":
+                    for (const v of a) {
+                        value.add(decode(v));
+                    }
+                    break;
+                case "Array":
+                    for (const [k, v] of b) {
+                        define(value, k, decode(v));
+                    }
+                    break;
+                case "Object":
+                    for (const [k, v] of a) {
+                        define(value, k, decode(v));
+                    }
+                    break;
+            }
+        }
+
+        return decode(root);
+    };
+})()
+"#;
+
+/// Serializes `value` into bytes that can be sent to any thread and read back
+/// with [`deserialize`] in any context. ArrayBuffers in `transfer` are
+/// detached from the source context once the value has been written.
+///
+/// Map, Set, Date and binary values can only be read back in a context built
+/// with the matching intrinsics.
+pub fn serialize(value: &JSValueRef, transfer: &[JSValueRef]) -> Result<Vec<u8>, QuickError> {
+    let ctx = ManuallyDrop::new(Context(value.ctx));
+
+    let mut args = vec![value.clone()];
+    args.extend(transfer.iter().cloned());
+
+    let encode = ctx.helper("clone.encode", ENCODE)?;
+    let encoded = function::call(encode, None, args).map_err(clone_error)?;
+    let bytes = ctx.write_object(&encoded, WRITE_FLAGS)?;
+
+    for buffer in transfer {
+        unsafe { sys::JS_DetachArrayBuffer(value.ctx, buffer.val) };
+    }
+
+    Ok(bytes)
+}
+
+pub fn deserialize(ctx: &Context, bytes: &[u8]) -> Result<JSValueRef, QuickError> {
+    let encoded = ctx.read_object(bytes, READ_FLAGS)?;
+
+    let decode = ctx.helper("clone.decode", DECODE)?;
+    function::call(decode, None, vec![encoded]).map_err(clone_error)
+}
+
+pub fn structured_clone(value: &JSValueRef, target: &Context) -> Result<JSValueRef, QuickError> {
+    structured_clone_with_transfer(value, target, &[])
+}
+
+pub fn structured_clone_with_transfer(
+    value: &JSValueRef,
+    target: &Context,
+    transfer: &[JSValueRef],
+) -> Result<JSValueRef, QuickError> {
+    deserialize(target, &serialize(value, transfer)?)
+}
+
+fn clone_error(e: QuickError) -> QuickError {
+    match e {
+        QuickError::CallError(e) => QuickError::CloneError(e),
+        e => e,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runtime::Runtime;
+
+    const GRAPH: &str = r#"
+(() => {
+    const value = {
+        n: 1,
+        s: "x",
+        list: [1, , 3],
+        date: new Date(5),
+        map: new Map([["k", 1]]),
+        set: new Set([2]),
+        bytes: new Uint8Array([1, 2]),
+    };
+    value.self = value;
+    Object.defineProperty(value, "__proto__", { value: 7, enumerable: true });
+    return value;
+})()
+"#;
+
+    const CHECK: &str = r#"
+v.n === 1 &&
+    v.s === "x" &&
+    v.list.length === 3 &&
+    !(1 in v.list) &&
+    v.date.getTime() === 5 &&
+    v.map.get("k") === 1 &&
+    v.set.has(2) &&
+    v.bytes[1] === 2 &&
+    v.self === v &&
+    Object.getPrototypeOf(v) === Object.prototype &&
+    Object.getOwnPropertyDescriptor(v, "__proto__").value === 7
+"#;
+
+    #[test]
+    fn round_trips_between_runtimes() {
+        let source_runtime = Runtime::default();
+        let source = Context::from(&source_runtime);
+        let target_runtime = Runtime::default();
+        let target = Context::from(&target_runtime);
+
+        let value = source.eval_global(GRAPH, "<test>").unwrap();
+        let cloned = structured_clone(&value, &target).unwrap();
+        target.global().set_property("v", cloned).unwrap();
+
+        let check = target.eval_global(CHECK, "<test>").unwrap();
+        assert!(check.to_bool().unwrap());
+    }
+
+    #[test]
+    fn rejects_functions() {
+        let runtime = Runtime::default();
+        let ctx = Context::from(&runtime);
+
+        let value = ctx.eval_global("({ f() {} })", "<test>").unwrap();
+        assert!(matches!(
+            serialize(&value, &[]),
+            Err(QuickError::CloneError(_))
+        ));
+    }
+}
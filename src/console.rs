@@ -0,0 +1,477 @@
+use crate::{context::Context, error::QuickError, function, value::JSValueRef};
+use log::Level;
+use quickjs_sys as sys;
+use std::{
+    cell::RefCell, collections::HashMap, ffi::CStr, mem::ManuallyDrop, rc::Rc, slice, time::Instant,
+};
+
+pub trait ConsoleSink {
+    fn write(&self, level: Level, target: &str, message: &str);
+}
+
+pub struct LogSink;
+
+impl ConsoleSink for LogSink {
+    fn write(&self, level: Level, target: &str, message: &str) {
+        log::log!(target: target, level, "{message}");
+    }
+}
+
+type Args = Vec<ManuallyDrop<JSValueRef>>;
+
+// Browser-style formatting for `%o` and non-string arguments. It only relies
+// on intrinsics that every context has, and checks for the optional ones.
+const INSPECT: &str = r#"
+(function () {
+    const { keys } = Object;
+    const toString = Object.prototype.toString;
+    const call = Function.prototype.call.bind(Function.prototype.call);
+    const isView = typeof ArrayBuffer === "function" ? ArrayBuffer.isView : () => false;
+
+    const tagOf = (v) => call(toString, v).slice(8, -1);
+
+    function quote(value) {
+        return "'" + value.split("\\").join("\\\\").split("'").join("\\'") + "'";
+    }
+
+    function key(name) {
+        for (let i = 0; i < name.length; i++) {
+            const c = name[i];
+            const letter = (c >= "a" && c <= "z") || (c >= "A" && c <= "Z") || c === "_" || c === "$";
+            if (!letter && !(i > 0 && c >= "0" && c <= "9")) {
+                return quote(name);
+            }
+        }
+        return name.length ? name : "''";
+    }
+
+    function list(prefix, items, open, close) {
+        return items.length ? `${prefix}${open} ${items.join(", ")} ${close}` : `${prefix}${open}${close}`;
+    }
+
+    function inspect(value, depth, seen) {
+        switch (typeof value) {
+            case "string":
+                return quote(value);
+            case "bigint":
+                return `${value}n`;
+            case "function":
+                return value.name ? `[Function: ${value.name}]` : "[Function (anonymous)]";
+            case "object":
+                break;
+            default:
+                return String(value);
+        }
+
+        if (value === null) {
+            return "null";
+        }
+
+        if (seen.includes(value)) {
+            return "[Circular]";
+        }
+
+        const tag = tagOf(value);
+        switch (tag) {
+            case "Date":
+                return isNaN(value) ? "Invalid Date" : value.toISOString();
+            case "RegExp":
+                return String(value);
+            case "Error":
+                return value.stack ? `${value}\n${value.stack}` : String(value);
+        }
+
+        if (depth > 2) {
+            return `[${tag}]`;
+        }
+
+        seen.push(value);
+        const nested = (v) => inspect(v, depth + 1, seen);
+        const items = [];
+        let result;
+
+        if (tag === "Array" || isView(value)) {
+            for (let i = 0; i < value.length; i++) {
+                items.push(i in value ? nested(value[i]) : "<empty>");
+            }
+            result = list(tag === "Array" ? "" : `${tag}(${value.length}) `, items, "[", "]");
+        } else if (tag === "Map") {
+            for (const [k, v] of value) {
+                items.push(`${nested(k)} => ${nested(v)}`);
+            }
+            result = list(`Map(${value.size}) `, items, "{", "}");
+        } else if (tag === "Set") {
+            for (const v of value) {
+                items.push(nested(v));
+            }
+            result = list(`Set(${value.size}) `, items, "{", "}");
+        } else {
+            for (const k of keys(value)) {
+                items.push(`${key(k)}: ${nested(value[k])}`);
+            }
+            result = list(tag === "Object" ? "" : `${tag} `, items, "{", "}");
+        }
+
+        seen.pop();
+        return result;
+    }
+
+    return (value) => inspect(value, 0, []);
+})()
+"#;
+
+const STACK: &str = r#"
+(function () {
+    return new Error().stack;
+})
+"#;
+
+pub(crate) fn install(ctx: &Context, sink: Rc<dyn ConsoleSink>) -> Result<(), QuickError> {
+    const LEVELS: [(&str, Level); 5] = [
+        ("log", Level::Info),
+        ("info", Level::Info),
+        ("warn", Level::Warn),
+        ("error", Level::Error),
+        ("debug", Level::Debug),
+    ];
+
+    let console = ctx.make_object();
+
+    for (name, level) in LEVELS {
+        let sink = sink.clone();
+        ctx.make_function(Some(console.clone()), name, 0, move |ctx, args| {
+            sink.write(level, &target(&ctx), &format(&ctx, &args));
+            ctx.make_undefined()
+        });
+    }
+
+    let sink_ = sink.clone();
+    ctx.make_function(Some(console.clone()), "trace", 0, move |ctx, args| {
+        let message = match format(&ctx, &args) {
+            v if v.is_empty() => String::from("Trace"),
+            v => format!("Trace: {v}"),
+        };
+        sink_.write(
+            Level::Trace,
+            &target(&ctx),
+            &format!("{message}\n{}", stack(&ctx)),
+        );
+
+        ctx.make_undefined()
+    });
+
+    let sink_ = sink.clone();
+    ctx.make_function(Some(console.clone()), "assert", 0, move |ctx, args| {
+        let ok = match args.first() {
+            Some(v) => unsafe { sys::JS_ToBool(ctx.0, v.val) > 0 },
+            None => false,
+        };
+
+        if !ok {
+            let message = match format(&ctx, args.get(1..).unwrap_or_default()) {
+                v if v.is_empty() => String::from("Assertion failed"),
+                v => format!("Assertion failed: {v}"),
+            };
+            sink_.write(Level::Error, &target(&ctx), &message);
+        }
+
+        ctx.make_undefined()
+    });
+
+    let sink_ = sink.clone();
+    ctx.make_function(Some(console.clone()), "table", 1, move |ctx, args| {
+        let message = match args.first() {
+            Some(v) => table(&ctx, v),
+            None => String::new(),
+        };
+        sink_.write(Level::Info, &target(&ctx), &message);
+
+        ctx.make_undefined()
+    });
+
+    let timers = Rc::new(RefCell::new(HashMap::<String, Instant>::new()));
+
+    let timers_ = timers.clone();
+    ctx.make_function(Some(console.clone()), "time", 0, move |ctx, args| {
+        timers_
+            .borrow_mut()
+            .insert(label(&ctx, &args), Instant::now());
+        ctx.make_undefined()
+    });
+
+    for (name, end) in [("timeLog", false), ("timeEnd", true)] {
+        let sink = sink.clone();
+        let timers = timers.clone();
+
+        ctx.make_function(Some(console.clone()), name, 0, move |ctx, args| {
+            let label = label(&ctx, &args);
+
+            let start = if end {
+                timers.borrow_mut().remove(&label)
+            } else {
+                timers.borrow().get(&label).copied()
+            };
+
+            let message = match start {
+                Some(start) => {
+                    let elapsed = start.elapsed().as_secs_f64() * 1000.0;
+                    format!("{label}: {elapsed:.3}ms")
+                }
+                None => format!("Timer '{label}' does not exist"),
+            };
+            sink.write(Level::Info, &target(&ctx), &message);
+
+            ctx.make_undefined()
+        });
+    }
+
+    ctx.global().set_property("console", console)
+}
+
+fn target(ctx: &Context) -> String {
+    unsafe {
+        let atom = sys::JS_GetScriptOrModuleName(ctx.0, 0);
+        if atom == sys::JS_ATOM_NULL {
+            return String::from("console");
+        }
+
+        let name = sys::JS_AtomToCString(ctx.0, atom);
+        sys::JS_FreeAtom(ctx.0, atom);
+
+        if name.is_null() {
+            return String::from("console");
+        }
+
+        let string = CStr::from_ptr(name).to_string_lossy().to_string();
+        sys::JS_FreeCString(ctx.0, name);
+
+        string
+    }
+}
+
+fn label(ctx: &Context, args: &Args) -> String {
+    match args.first() {
+        Some(v) if v.tag() != sys::JS_TAG_UNDEFINED => text(ctx, v),
+        _ => String::from("default"),
+    }
+}
+
+fn format(ctx: &Context, args: &[ManuallyDrop<JSValueRef>]) -> String {
+    let mut buf = String::new();
+    let mut rest = args.iter();
+
+    if let Some(first) = args.first().filter(|v| v.tag() == sys::JS_TAG_STRING) {
+        rest.next();
+
+        let mut chars = text(ctx, first).chars().collect::<Vec<_>>().into_iter();
+        while let Some(c) = chars.next() {
+            if c != '%' {
+                buf.push(c);
+                continue;
+            }
+
+            let spec = match chars.as_slice().first() {
+                Some(spec) if "sdifoOjc%".contains(*spec) => *spec,
+                _ => {
+                    buf.push(c);
+                    continue;
+                }
+            };
+            chars.next();
+
+            if spec == '%' {
+                buf.push('%');
+                continue;
+            }
+
+            match rest.next() {
+                Some(_) if spec == 'c' => {}
+                Some(v) => buf.push_str(&match spec {
+                    's' => display(ctx, v),
+                    'd' | 'i' => number(ctx, v, true),
+                    'f' => number(ctx, v, false),
+                    _ => inspect(ctx, v),
+                }),
+                None => {
+                    buf.push('%');
+                    buf.push(spec);
+                }
+            }
+        }
+    }
+
+    for value in rest {
+        if !buf.is_empty() {
+            buf.push(' ');
+        }
+        buf.push_str(&display(ctx, value));
+    }
+
+    buf
+}
+
+fn number(ctx: &Context, value: &JSValueRef, integer: bool) -> String {
+    let mut number = 0.0;
+    if unsafe { sys::JS_ToFloat64(ctx.0, &mut number, value.val) } < 0 {
+        clear_exception(ctx);
+        return String::from("NaN");
+    }
+
+    if integer && number.is_finite() {
+        format!("{}", number.trunc())
+    } else {
+        format!("{number}")
+    }
+}
+
+fn display(ctx: &Context, value: &JSValueRef) -> String {
+    if value.tag() == sys::JS_TAG_STRING {
+        text(ctx, value)
+    } else {
+        inspect(ctx, value)
+    }
+}
+
+fn inspect(ctx: &Context, value: &JSValueRef) -> String {
+    match call(ctx, "console.inspect", INSPECT, value.clone()) {
+        Ok(v) => v,
+        Err(_) => {
+            clear_exception(ctx);
+            text(ctx, value)
+        }
+    }
+}
+
+/// The caller's stack, without the frame of the helper that captured it.
+fn stack(ctx: &Context) -> String {
+    match call(ctx, "console.stack", STACK, ctx.make_undefined()) {
+        Ok(v) => v.lines().skip(1).collect::<Vec<_>>().join("\n"),
+        Err(_) => {
+            clear_exception(ctx);
+            String::new()
+        }
+    }
+}
+
+fn call(
+    ctx: &Context,
+    name: &'static str,
+    source: &str,
+    arg: JSValueRef,
+) -> Result<String, QuickError> {
+    let helper = ctx.helper(name, source)?;
+
+    function::call(helper, None, vec![arg])?.to_string()
+}
+
+fn table(ctx: &Context, data: &JSValueRef) -> String {
+    if data.tag() != sys::JS_TAG_OBJECT {
+        return display(ctx, data);
+    }
+
+    let rows = match data.keys() {
+        Ok(v) => v,
+        Err(e) => return e.to_string(),
+    };
+
+    let mut columns: Vec<String> = Vec::new();
+    let mut values = false;
+    let mut cells = Vec::with_capacity(rows.len());
+
+    for row in rows {
+        let value = match data.property(&row) {
+            Ok(v) => v,
+            Err(e) => return e.to_string(),
+        };
+
+        let mut cell = HashMap::new();
+
+        if value.tag() == sys::JS_TAG_OBJECT && !value.is_function() {
+            for key in value.keys().unwrap_or_default() {
+                if let Ok(v) = value.property(&key) {
+                    cell.insert(key.clone(), inspect(ctx, &v));
+                }
+
+                if !columns.contains(&key) {
+                    columns.push(key);
+                }
+            }
+        } else {
+            values = true;
+            cell.insert(String::new(), inspect(ctx, &value));
+        }
+
+        cells.push((row, cell));
+    }
+
+    let mut header = vec![String::from("(index)")];
+    header.extend(columns.iter().cloned());
+    if values {
+        header.push(String::from("Values"));
+        columns.push(String::new());
+    }
+
+    let lines: Vec<Vec<String>> = cells
+        .into_iter()
+        .map(|(row, mut cell)| {
+            let mut line = vec![row];
+            line.extend(columns.iter().map(|v| cell.remove(v).unwrap_or_default()));
+            line
+        })
+        .collect();
+
+    let widths: Vec<usize> = (0..header.len())
+        .map(|i| {
+            lines
+                .iter()
+                .map(|v| v[i].chars().count())
+                .chain([header[i].chars().count()])
+                .max()
+                .unwrap_or_default()
+        })
+        .collect();
+
+    let render = |line: &[String]| {
+        let cells: Vec<String> = line
+            .iter()
+            .zip(&widths)
+            .map(|(v, width)| format!(" {v:width$} "))
+            .collect();
+        format!("|{}|", cells.join("|"))
+    };
+    let separator = format!(
+        "|{}|",
+        widths
+            .iter()
+            .map(|v| "-".repeat(v + 2))
+            .collect::<Vec<_>>()
+            .join("|")
+    );
+
+    let mut table = vec![render(&header), separator];
+    table.extend(lines.iter().map(|v| render(v)));
+    table.join("\n")
+}
+
+fn text(ctx: &Context, value: &JSValueRef) -> String {
+    unsafe {
+        let mut len = 0;
+        let data = sys::JS_ToCStringLen2(ctx.0, &mut len, value.val, 0);
+
+        if data.is_null() {
+            clear_exception(ctx);
+            return String::new();
+        }
+
+        let string = String::from_utf8_lossy(slice::from_raw_parts(data as *const u8, len));
+        let string = string.to_string();
+        sys::JS_FreeCString(ctx.0, data);
+
+        string
+    }
+}
+
+fn clear_exception(ctx: &Context) {
+    let value = unsafe { sys::JS_GetException(ctx.0) };
+    drop(JSValueRef::from_value(ctx.0, value));
+}
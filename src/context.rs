@@ -1,7 +1,12 @@
 use crate::{
+    bytecode,
+    console::{self, ConsoleSink, LogSink},
     error::QuickError,
-    runtime::Runtime,
+    require,
+    runtime::{self, Runtime},
+    timer,
     value::{Exception, JSValueRef},
+    worker,
 };
 use log::error;
 use quickjs_sys as sys;
@@ -10,6 +15,7 @@ use std::{
     ffi::{c_double, c_int, c_void, CString},
     mem::ManuallyDrop,
     ptr::{self, slice_from_raw_parts_mut},
+    rc::Rc,
     slice,
 };
 
@@ -25,6 +31,7 @@ impl From<&Runtime> for Context {
     fn from(value: &Runtime) -> Self {
         let ctx = unsafe {
             let ctx = sys::JS_NewContext(value.0);
+            sys::JS_SetContextOpaque(ctx, value.1 as *mut c_void);
 
             sys::JS_AddIntrinsicRegExpCompiler(ctx);
 
@@ -35,6 +42,221 @@ impl From<&Runtime> for Context {
     }
 }
 
+const DENY_CODE_GENERATION: &str = r#"
+(function (kinds) {
+    for (const kind of kinds) {
+        const prototype = Object.getPrototypeOf(kind);
+        const deny = function () {
+            throw new EvalError("code generation from strings is disabled");
+        };
+        deny.prototype = prototype;
+
+        Object.defineProperty(prototype, "constructor", {
+            value: deny,
+            writable: false,
+            enumerable: false,
+            configurable: false,
+        });
+
+        if (prototype === Function.prototype) {
+            globalThis.Function = deny;
+        }
+    }
+})
+"#;
+
+const FREEZE_PROTOTYPES: &str = r#"
+(function () {
+    let prototype = Object.getPrototypeOf(globalThis);
+    while (prototype !== null) {
+        Object.freeze(prototype);
+        prototype = Object.getPrototypeOf(prototype);
+    }
+})();
+"#;
+
+#[derive(Clone, Copy)]
+pub struct ContextBuilder {
+    date: bool,
+    regexp: bool,
+    json: bool,
+    proxy: bool,
+    map_set: bool,
+    typed_arrays: bool,
+    promise: bool,
+    bigint: bool,
+    weak_ref: bool,
+    eval: bool,
+    function_constructor: bool,
+}
+
+impl ContextBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn minimal() -> Self {
+        ContextBuilder {
+            date: false,
+            regexp: false,
+            json: false,
+            proxy: false,
+            map_set: false,
+            typed_arrays: false,
+            promise: false,
+            bigint: false,
+            weak_ref: false,
+            eval: false,
+            function_constructor: false,
+        }
+    }
+
+    pub fn date(mut self, enabled: bool) -> Self {
+        self.date = enabled;
+        self
+    }
+
+    pub fn regexp(mut self, enabled: bool) -> Self {
+        self.regexp = enabled;
+        self
+    }
+
+    pub fn json(mut self, enabled: bool) -> Self {
+        self.json = enabled;
+        self
+    }
+
+    pub fn proxy(mut self, enabled: bool) -> Self {
+        self.proxy = enabled;
+        self
+    }
+
+    pub fn map_set(mut self, enabled: bool) -> Self {
+        self.map_set = enabled;
+        self
+    }
+
+    pub fn typed_arrays(mut self, enabled: bool) -> Self {
+        self.typed_arrays = enabled;
+        self
+    }
+
+    pub fn promise(mut self, enabled: bool) -> Self {
+        self.promise = enabled;
+        self
+    }
+
+    pub fn bigint(mut self, enabled: bool) -> Self {
+        self.bigint = enabled;
+        self
+    }
+
+    pub fn weak_ref(mut self, enabled: bool) -> Self {
+        self.weak_ref = enabled;
+        self
+    }
+
+    /// Controls the global `eval`. The host can always compile code with
+    /// [`Context::eval`], so the eval intrinsic itself stays installed.
+    pub fn eval(mut self, enabled: bool) -> Self {
+        self.eval = enabled;
+        self
+    }
+
+    pub fn function_constructor(mut self, enabled: bool) -> Self {
+        self.function_constructor = enabled;
+        self
+    }
+
+    pub fn build(&self, runtime: &Runtime) -> Result<Context, QuickError> {
+        let ctx = unsafe { sys::JS_NewContextRaw(runtime.0) };
+        if ctx.is_null() {
+            return Err(QuickError::ContextError(String::from("JS_NewContextRaw")));
+        }
+
+        unsafe {
+            sys::JS_SetContextOpaque(ctx, runtime.1 as *mut c_void);
+
+            sys::JS_AddIntrinsicBaseObjects(ctx);
+            sys::JS_AddIntrinsicEval(ctx);
+
+            if self.date {
+                sys::JS_AddIntrinsicDate(ctx);
+            }
+
+            if self.regexp {
+                sys::JS_AddIntrinsicRegExpCompiler(ctx);
+                sys::JS_AddIntrinsicRegExp(ctx);
+            }
+
+            if self.json {
+                sys::JS_AddIntrinsicJSON(ctx);
+            }
+
+            if self.proxy {
+                sys::JS_AddIntrinsicProxy(ctx);
+            }
+
+            if self.map_set {
+                sys::JS_AddIntrinsicMapSet(ctx);
+            }
+
+            if self.typed_arrays {
+                sys::JS_AddIntrinsicTypedArrays(ctx);
+            }
+
+            if self.promise {
+                sys::JS_AddIntrinsicPromise(ctx);
+            }
+
+            if self.bigint {
+                sys::JS_AddIntrinsicBigInt(ctx);
+            }
+
+            if self.weak_ref {
+                sys::JS_AddIntrinsicWeakRef(ctx);
+            }
+        }
+
+        let context = Context(ctx);
+
+        if !self.eval {
+            context.remove_globals(["eval"])?;
+        }
+
+        if !self.function_constructor {
+            let kinds = if self.promise {
+                "[function () {}, function* () {}, async function () {}, async function* () {}]"
+            } else {
+                "[function () {}, function* () {}]"
+            };
+
+            let source = format!("{}({kinds});\n", DENY_CODE_GENERATION.trim_end());
+            context.eval_global(source, "<sandbox>")?;
+        }
+
+        Ok(context)
+    }
+}
+
+impl Default for ContextBuilder {
+    fn default() -> Self {
+        ContextBuilder {
+            date: true,
+            regexp: true,
+            json: true,
+            proxy: true,
+            map_set: true,
+            typed_arrays: true,
+            promise: true,
+            bigint: true,
+            weak_ref: true,
+            eval: true,
+            function_constructor: true,
+        }
+    }
+}
+
 impl Context {
     pub fn eval_module(
         &self,
@@ -69,7 +291,7 @@ impl Context {
             let value = sys::JS_Eval(
                 self.0,
                 c_source.as_ptr(),
-                source.as_ref().len() - 1,
+                source.as_ref().len(),
                 c_name.as_ptr(),
                 flags,
             );
@@ -79,13 +301,316 @@ impl Context {
                 let value = sys::JS_GetException(self.0);
                 let value = JSValueRef::from_value(self.0, value);
 
-                Err(QuickError::EvalError(Exception(value).to_string()))
+                Err(Exception(value).into_error(QuickError::EvalError))
             } else {
                 Ok(value)
             }
         }
     }
 
+    pub fn compile_to_bytecode(
+        &self,
+        source: impl AsRef<str>,
+        name: impl AsRef<str>,
+    ) -> Result<Vec<u8>, QuickError> {
+        let c_source = match CString::new(source.as_ref()) {
+            Ok(v) => v,
+            Err(e) => return Err(QuickError::CStringError(e.to_string())),
+        };
+
+        let module = unsafe { sys::JS_DetectModule(c_source.as_ptr(), source.as_ref().len()) };
+        let flags = if module != 0 {
+            sys::JS_EVAL_TYPE_MODULE
+        } else {
+            sys::JS_EVAL_TYPE_GLOBAL
+        } | sys::JS_EVAL_FLAG_COMPILE_ONLY;
+
+        let value = self.eval(source, name, flags as i32)?;
+        self.write_bytecode(&value)
+    }
+
+    pub(crate) fn write_bytecode(&self, value: &JSValueRef) -> Result<Vec<u8>, QuickError> {
+        let bytecode = self.write_object(value, sys::JS_WRITE_OBJ_BYTECODE as i32)?;
+        Ok(bytecode::encode(&bytecode))
+    }
+
+    pub(crate) fn write_object(
+        &self,
+        value: &JSValueRef,
+        flags: i32,
+    ) -> Result<Vec<u8>, QuickError> {
+        unsafe {
+            let mut len = 0;
+            let buf = sys::JS_WriteObject(self.0, &mut len, value.val, flags);
+
+            if buf.is_null() {
+                let value = sys::JS_GetException(self.0);
+                let value = JSValueRef::from_value(self.0, value);
+
+                return Err(Exception(value).into_error(QuickError::BytecodeError));
+            }
+
+            let bytes = slice::from_raw_parts(buf, len).to_vec();
+            sys::js_free(self.0, buf as *mut c_void);
+
+            Ok(bytes)
+        }
+    }
+
+    /// Evaluates an internal script the first time the context needs it and
+    /// keeps the result until the context is dropped.
+    pub(crate) fn helper(
+        &self,
+        name: &'static str,
+        source: &str,
+    ) -> Result<JSValueRef, QuickError> {
+        let state = unsafe { runtime::State::from_context(self.0) };
+
+        if let Some(helper) = state.helpers.borrow().get(&(self.0, name)) {
+            return Ok(helper.clone());
+        }
+
+        let helper = self.eval_global(source, format!("<{name}>"))?;
+        state
+            .helpers
+            .borrow_mut()
+            .insert((self.0, name), helper.clone());
+
+        Ok(helper)
+    }
+
+    pub(crate) fn read_object(&self, bytes: &[u8], flags: i32) -> Result<JSValueRef, QuickError> {
+        let value = unsafe { sys::JS_ReadObject(self.0, bytes.as_ptr(), bytes.len(), flags) };
+        let value = JSValueRef::from_value(self.0, value);
+
+        if value.is_exception() {
+            let value = unsafe { sys::JS_GetException(self.0) };
+            let value = JSValueRef::from_value(self.0, value);
+
+            Err(Exception(value).into_error(QuickError::BytecodeError))
+        } else {
+            Ok(value)
+        }
+    }
+
+    /// Scripts are evaluated and their completion value returned. Module
+    /// bytecode is returned linked but not yet evaluated; pass it to
+    /// [`Module::new`](crate::module::Module::new) to run it.
+    pub fn load_bytecode(&self, bytecode: &[u8]) -> Result<JSValueRef, QuickError> {
+        let value = self.read_bytecode(bytecode)?;
+
+        if value.tag() == sys::JS_TAG_MODULE {
+            return Ok(value);
+        }
+
+        unsafe {
+            let value = sys::JS_EvalFunction(self.0, value.val());
+            let value = JSValueRef::from_value(self.0, value);
+
+            if value.is_exception() {
+                let value = sys::JS_GetException(self.0);
+                let value = JSValueRef::from_value(self.0, value);
+
+                Err(Exception(value).into_error(QuickError::EvalError))
+            } else {
+                Ok(value)
+            }
+        }
+    }
+
+    pub(crate) fn read_bytecode(&self, bytecode: &[u8]) -> Result<JSValueRef, QuickError> {
+        const FLAGS: i32 = sys::JS_READ_OBJ_BYTECODE as i32;
+
+        let bytecode = bytecode::decode(bytecode)?;
+
+        unsafe {
+            let value = sys::JS_ReadObject(self.0, bytecode.as_ptr(), bytecode.len(), FLAGS);
+            let value = JSValueRef::from_value(self.0, value);
+
+            if !value.is_exception()
+                && (value.tag() != sys::JS_TAG_MODULE
+                    || sys::JS_ResolveModule(self.0, value.val) == 0)
+            {
+                return Ok(value);
+            }
+
+            let value = sys::JS_GetException(self.0);
+            let value = JSValueRef::from_value(self.0, value);
+
+            Err(Exception(value).into_error(QuickError::BytecodeError))
+        }
+    }
+
+    pub fn await_promise(&self, value: JSValueRef) -> Result<JSValueRef, QuickError> {
+        let rt = unsafe { sys::JS_GetRuntime(self.0) };
+
+        loop {
+            let state = unsafe { sys::JS_PromiseState(self.0, value.val) };
+
+            if state == sys::JSPromiseStateEnum_JS_PROMISE_PENDING {
+                if !runtime::execute_pending_job(rt)? {
+                    return Err(QuickError::JobError(String::from("promise never settled")));
+                }
+            } else if state == sys::JSPromiseStateEnum_JS_PROMISE_FULFILLED {
+                let value = unsafe { sys::JS_PromiseResult(self.0, value.val) };
+                return Ok(JSValueRef::from_value(self.0, value));
+            } else if state == sys::JSPromiseStateEnum_JS_PROMISE_REJECTED {
+                let value = unsafe { sys::JS_PromiseResult(self.0, value.val) };
+                let value = JSValueRef::from_value(self.0, value);
+
+                return Err(Exception(value).into_error(QuickError::EvalError));
+            } else {
+                return Ok(value);
+            }
+        }
+    }
+
+    pub fn enable_require(&self) -> Result<(), QuickError> {
+        require::install(self)
+    }
+
+    pub fn enable_console(&self, sink: Option<Box<dyn ConsoleSink>>) -> Result<(), QuickError> {
+        let sink: Rc<dyn ConsoleSink> = match sink {
+            Some(v) => Rc::from(v),
+            None => Rc::new(LogSink),
+        };
+
+        console::install(self, sink)
+    }
+
+    #[cfg(feature = "libc")]
+    pub fn enable_libc(&self) -> Result<(), QuickError> {
+        unsafe {
+            let state = runtime::State::from_context(self.0);
+            if !state.libc.get() {
+                return Err(QuickError::ContextError(String::from(
+                    "Runtime::enable_libc must be called first",
+                )));
+            }
+
+            if sys::js_init_module_std(self.0, b"std\0".as_ptr() as *const _).is_null()
+                || sys::js_init_module_os(self.0, b"os\0".as_ptr() as *const _).is_null()
+            {
+                let value = sys::JS_GetException(self.0);
+                let value = JSValueRef::from_value(self.0, value);
+
+                return Err(Exception(value).into_error(QuickError::ModuleError));
+            }
+
+            sys::js_std_add_helpers(self.0, 0, ptr::null_mut());
+        }
+
+        Ok(())
+    }
+
+    #[cfg(feature = "libc")]
+    pub fn run_libc_loop(&self) {
+        unsafe {
+            sys::js_std_loop(self.0);
+        }
+    }
+
+    pub fn enable_timers(&self) -> Result<(), QuickError> {
+        timer::install(self)
+    }
+
+    pub fn enable_workers(&self) -> Result<(), QuickError> {
+        worker::install_host(self)
+    }
+
+    /// QuickJS fixes a context's global object when the context is created and
+    /// exposes no way to swap it, so the global can only be customized in
+    /// place; build a new context for a different realm.
+    pub fn global(&self) -> JSValueRef {
+        let global = unsafe { sys::JS_GetGlobalObject(self.0) };
+        JSValueRef::from_value(self.0, global)
+    }
+
+    /// Defines every top-level field of `values`, which must serialize to a
+    /// JSON object, as a global.
+    pub fn register_globals<T>(&self, values: T) -> Result<(), QuickError>
+    where
+        T: Serialize,
+    {
+        let values = self
+            .make_json(values)
+            .map_err(|e| QuickError::ContextError(e.to_string()))?;
+
+        if values.tag() != sys::JS_TAG_OBJECT {
+            return Err(QuickError::UnsupportedTypeError(values.tag()));
+        }
+
+        let global = self.global();
+        for key in values.keys()? {
+            global.set_property(&key, values.property(&key)?)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn set_globals<I, K>(&self, values: I) -> Result<(), QuickError>
+    where
+        I: IntoIterator<Item = (K, JSValueRef)>,
+        K: AsRef<str>,
+    {
+        let global = self.global();
+        values
+            .into_iter()
+            .try_for_each(|(key, value)| global.set_property(key, value))
+    }
+
+    /// Deletes built-ins such as `eval` or `Atomics` from the global object.
+    pub fn remove_globals<I, K>(&self, names: I) -> Result<(), QuickError>
+    where
+        I: IntoIterator<Item = K>,
+        K: AsRef<str>,
+    {
+        let global = self.global();
+
+        for name in names {
+            let c_name = match CString::new(name.as_ref()) {
+                Ok(v) => v,
+                Err(e) => return Err(QuickError::CStringError(e.to_string())),
+            };
+
+            let deleted = unsafe {
+                let atom = sys::JS_NewAtom(self.0, c_name.as_ptr());
+                let deleted = sys::JS_DeleteProperty(self.0, global.val, atom, 0);
+                sys::JS_FreeAtom(self.0, atom);
+                deleted
+            };
+
+            if deleted < 0 {
+                let value = unsafe { sys::JS_GetException(self.0) };
+                let value = JSValueRef::from_value(self.0, value);
+
+                return Err(Exception(value).into_error(QuickError::ContextError));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Freezes every prototype of the global object; the global object itself
+    /// stays extensible.
+    pub fn freeze_global_prototypes(&self) -> Result<(), QuickError> {
+        self.eval_global(FREEZE_PROTOTYPES, "<sandbox>").map(|_| ())
+    }
+
+    pub fn throw_error(&self, message: impl AsRef<str>) -> JSValueRef {
+        let error = unsafe { sys::JS_NewError(self.0) };
+        let error = JSValueRef::from_value(self.0, error);
+
+        if let Ok(message) = self.make_string(message) {
+            if let Err(e) = error.set_property("message", message) {
+                error!("{e}");
+            }
+        }
+
+        let value = unsafe { sys::JS_Throw(self.0, error.val()) };
+        JSValueRef::from_value(self.0, value)
+    }
+
     pub fn make_object(&self) -> JSValueRef {
         let value = unsafe { sys::JS_NewObject(self.0) };
         JSValueRef::from_value(self.0, value)
@@ -187,6 +712,25 @@ impl Context {
         value: F,
     ) where
         F: Fn(ManuallyDrop<Context>, Vec<ManuallyDrop<JSValueRef>>) -> JSValueRef,
+    {
+        let name = format!("{}\0", name.as_ref());
+        let func = self.new_function(args, value).val();
+
+        unsafe {
+            let this = match this {
+                Some(v) => v,
+                None => self.global(),
+            }
+            .val();
+            sys::JS_SetPropertyStr(self.0, this, name.as_ptr() as _, func);
+
+            drop(JSValueRef::from_value(self.0, this));
+        }
+    }
+
+    pub fn new_function<F>(&self, args: i32, value: F) -> JSValueRef
+    where
+        F: Fn(ManuallyDrop<Context>, Vec<ManuallyDrop<JSValueRef>>) -> JSValueRef,
     {
         unsafe extern "C" fn inner<F>(
             ctx: *mut sys::JSContext,
@@ -219,29 +763,28 @@ impl Context {
             closure(ManuallyDrop::new(Context(ctx)), args).val()
         }
 
-        let name = format!("{}\0", name.as_ref());
-
         let data = Box::into_raw(Box::new(value));
         let data = unsafe { self.make_ptr(data as *mut c_void) }.val();
         let data = (&data) as *const sys::JSValue as *mut sys::JSValue;
 
-        unsafe {
-            let func = sys::JS_NewCFunctionData(self.0, Some(inner::<F>), args, 0, 1, data);
-
-            let this = match this {
-                Some(v) => v.val(),
-                None => sys::JS_GetGlobalObject(self.0),
-            };
-            sys::JS_SetPropertyStr(self.0, this, name.as_ptr() as _, func);
-
-            drop(JSValueRef::from_value(self.0, this));
-        }
+        let func = unsafe { sys::JS_NewCFunctionData(self.0, Some(inner::<F>), args, 0, 1, data) };
+        JSValueRef::from_value(self.0, func)
     }
 }
 
 impl Drop for Context {
     fn drop(&mut self) {
         unsafe {
+            if !sys::JS_GetContextOpaque(self.0).is_null() {
+                let state = runtime::State::from_context(self.0);
+                state
+                    .helpers
+                    .borrow_mut()
+                    .retain(|(ctx, _), _| *ctx != self.0);
+                state.timers.borrow_mut().remove_context(self.0);
+                state.workers.borrow_mut().remove_context(self.0);
+            }
+
             sys::JS_FreeContext(self.0);
         }
     }
@@ -0,0 +1,83 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+/// Walks `dir` from a build script and writes the `(name, bytes)` table that
+/// [`embed_dir!`](crate::embed_dir) includes. Modules are named by their path
+/// relative to `dir` with `/` separators, and cargo is told to rebuild when
+/// any of them changes.
+pub fn write_table(dir: impl AsRef<Path>, out: impl AsRef<Path>) -> io::Result<()> {
+    let dir = fs::canonicalize(dir)?;
+
+    let mut files = Vec::new();
+    walk(&dir, &mut files)?;
+    files.sort();
+
+    let mut table = String::from("&[\n");
+    for file in &files {
+        let name = file
+            .strip_prefix(&dir)
+            .unwrap_or(file)
+            .components()
+            .map(|v| v.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+
+        table.push_str(&format!(
+            "    ({name:?}, &include_bytes!({:?})[..]),\n",
+            file.to_string_lossy()
+        ));
+    }
+    table.push(']');
+
+    println!("cargo:rerun-if-changed={}", dir.display());
+    fs::write(out, table)
+}
+
+fn walk(dir: &Path, files: &mut Vec<PathBuf>) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+
+        if path.is_dir() {
+            walk(&path, files)?;
+        } else {
+            println!("cargo:rerun-if-changed={}", path.display());
+            files.push(path);
+        }
+    }
+
+    Ok(())
+}
+
+/// Builds an [`EmbeddedLoader`](crate::loader::EmbeddedLoader) from a table
+/// written to `OUT_DIR` by [`write_table`].
+#[macro_export]
+macro_rules! embed_dir {
+    ($file:literal) => {
+        $crate::loader::EmbeddedLoader::new(include!(concat!(env!("OUT_DIR"), "/", $file)))
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn table_names_files_relative_to_the_directory() {
+        let dir = std::env::temp_dir().join(format!("quick-rs-embed-{}", std::process::id()));
+        fs::create_dir_all(dir.join("lib")).unwrap();
+        fs::write(dir.join("main.js"), "").unwrap();
+        fs::write(dir.join("lib").join("util.js"), "").unwrap();
+
+        let out = dir.join("table.rs");
+        write_table(dir.join("lib").join(".."), &out).unwrap();
+        let table = fs::read_to_string(&out).unwrap();
+
+        let lib = table.find("(\"lib/util.js\", ").unwrap();
+        let main = table.find("(\"main.js\", ").unwrap();
+        assert!(lib < main);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -2,14 +2,34 @@ use thiserror::Error;
 
 #[derive(Error, Debug)]
 pub enum QuickError {
+    #[error("ContextError {0}")]
+    ContextError(String),
     #[error("CodeError {0}")]
     CodeError(String),
     #[error("EvalError {0}")]
     EvalError(String),
     #[error("CallError {0}")]
     CallError(String),
+    #[error("JobError {0}")]
+    JobError(String),
     #[error("CStringError {0}")]
     CStringError(String),
+    #[error("BytecodeError {0}")]
+    BytecodeError(String),
+    #[error("LinkError {0}")]
+    LinkError(String),
+    #[error("ModuleError {0}")]
+    ModuleError(String),
+    #[error("ImportMapError {0}")]
+    ImportMapError(String),
+    #[error("CloneError {0}")]
+    CloneError(String),
+    #[error("PoolError {0}")]
+    PoolError(String),
+    #[error("WorkerError {0}")]
+    WorkerError(String),
+    #[error("OutOfMemory")]
+    OutOfMemory,
     #[error("UnsupportedTypeError {0}")]
     UnsupportedTypeError(i32),
 }
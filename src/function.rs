@@ -45,9 +45,22 @@ impl Function {
             let value = unsafe { sys::JS_GetException(self.value.ctx) };
             let value = JSValueRef::from_value(self.value.ctx, value);
 
-            Err(QuickError::CallError(Exception(value).to_string()))
+            Err(Exception(value).into_error(QuickError::CallError))
         } else {
             Ok(value)
         }
     }
 }
+
+/// Wraps `value`, reporting a failure as [`QuickError::CallError`].
+pub(crate) fn wrap(value: JSValueRef) -> Result<Function, QuickError> {
+    Function::new(value).map_err(|e| QuickError::CallError(e.to_string()))
+}
+
+pub(crate) fn call(
+    value: JSValueRef,
+    this: Option<JSValueRef>,
+    args: Vec<JSValueRef>,
+) -> Result<JSValueRef, QuickError> {
+    wrap(value)?.call(this, args)
+}
@@ -0,0 +1,233 @@
+use crate::{
+    error::QuickError,
+    resolver::{is_relative, normalize},
+};
+use log::warn;
+use serde::Deserialize;
+use serde_json::Value;
+use std::{collections::HashMap, fs, path::Path};
+
+// `None` addresses are entries the spec nulls out; they block resolution.
+type SpecifierMap = Vec<(String, Option<String>)>;
+
+#[derive(Deserialize)]
+struct Raw {
+    #[serde(default)]
+    imports: HashMap<String, Value>,
+    #[serde(default)]
+    scopes: HashMap<String, HashMap<String, Value>>,
+}
+
+#[derive(Default)]
+pub struct ImportMap {
+    imports: SpecifierMap,
+    scopes: Vec<(String, SpecifierMap)>,
+}
+
+impl ImportMap {
+    pub fn from_json(json: impl AsRef<str>) -> Result<Self, QuickError> {
+        Self::parse(json.as_ref(), Path::new(""))
+    }
+
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, QuickError> {
+        let path = path.as_ref();
+
+        let json = match fs::read_to_string(path) {
+            Ok(v) => v,
+            Err(e) => return Err(QuickError::ImportMapError(format!("{path:?}: {e}"))),
+        };
+
+        Self::parse(&json, path.parent().unwrap_or(Path::new("")))
+    }
+
+    /// Maps `name` imported from `base`. Returns `None` when no entry matches
+    /// and an error when the matching entry is null.
+    pub fn resolve(&self, base: &str, name: &str) -> Result<Option<String>, QuickError> {
+        let specifier = if is_relative(name) {
+            let dir = Path::new(base).parent().unwrap_or(Path::new(""));
+            normalize(&dir.join(name)).to_string_lossy().to_string()
+        } else {
+            name.to_string()
+        };
+
+        let mapped = self
+            .scopes
+            .iter()
+            .filter(|(scope, _)| base == scope || (scope.ends_with('/') && base.starts_with(scope)))
+            .find_map(|(_, imports)| lookup(imports, &specifier))
+            .or_else(|| lookup(&self.imports, &specifier));
+
+        match mapped {
+            Some(Some(v)) => Ok(Some(v)),
+            Some(None) => Err(QuickError::ImportMapError(format!(
+                "{name}: blocked by a null entry"
+            ))),
+            None => Ok(None),
+        }
+    }
+
+    fn parse(json: &str, base: &Path) -> Result<Self, QuickError> {
+        let raw: Raw = match serde_json::from_str(json) {
+            Ok(v) => v,
+            Err(e) => return Err(QuickError::ImportMapError(e.to_string())),
+        };
+
+        let mut scopes: Vec<_> = raw
+            .scopes
+            .into_iter()
+            .map(|(scope, imports)| (address(base, &scope), sort(base, imports)))
+            .collect();
+        scopes.sort_by(|(a, _), (b, _)| b.len().cmp(&a.len()));
+
+        Ok(ImportMap {
+            imports: sort(base, raw.imports),
+            scopes,
+        })
+    }
+}
+
+fn address(base: &Path, address: &str) -> String {
+    if !is_relative(address) {
+        return address.to_string();
+    }
+
+    let mut path = normalize(&base.join(address)).to_string_lossy().to_string();
+
+    if address.ends_with('/') && !path.ends_with('/') {
+        path.push('/');
+    }
+
+    path
+}
+
+/// Invalid addresses are kept as null entries with a warning, as the import
+/// maps spec does, so they block the specifier instead of failing the map.
+fn sort(base: &Path, imports: HashMap<String, Value>) -> SpecifierMap {
+    let mut imports: SpecifierMap = imports
+        .into_iter()
+        .map(|(key, value)| {
+            let value = match value {
+                Value::String(v) if key.ends_with('/') && !v.ends_with('/') => {
+                    warn!("import map: {key}: address {v} must end with a slash");
+                    None
+                }
+                Value::String(v) => Some(address(base, &v)),
+                Value::Null => None,
+                v => {
+                    warn!("import map: {key}: address must be a string, got {v}");
+                    None
+                }
+            };
+
+            (address(base, &key), value)
+        })
+        .collect();
+    imports.sort_by(|(a, _), (b, _)| b.len().cmp(&a.len()));
+
+    imports
+}
+
+fn lookup(imports: &SpecifierMap, specifier: &str) -> Option<Option<String>> {
+    imports.iter().find_map(|(key, value)| {
+        if key == specifier {
+            Some(value.clone())
+        } else if key.ends_with('/') {
+            specifier
+                .strip_prefix(key.as_str())
+                .map(|rest| value.as_ref().map(|v| format!("{v}{rest}")))
+        } else {
+            None
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn map(imports: &[(&str, &str)]) -> SpecifierMap {
+        let imports = imports
+            .iter()
+            .map(|(k, v)| (k.to_string(), Value::from(*v)))
+            .collect();
+
+        sort(Path::new(""), imports)
+    }
+
+    fn mapped(imports: &SpecifierMap, specifier: &str) -> Option<String> {
+        lookup(imports, specifier).map(|v| v.expect("blocked"))
+    }
+
+    #[test]
+    fn lookup_prefers_exact_then_longest_prefix() {
+        let imports = map(&[
+            ("lodash", "/vendor/lodash/index.js"),
+            ("lodash/", "/vendor/lodash/"),
+            ("lodash/fp/", "/vendor/lodash-fp/"),
+        ]);
+
+        assert_eq!(
+            mapped(&imports, "lodash").as_deref(),
+            Some("/vendor/lodash/index.js")
+        );
+        assert_eq!(
+            mapped(&imports, "lodash/map.js").as_deref(),
+            Some("/vendor/lodash/map.js")
+        );
+        assert_eq!(
+            mapped(&imports, "lodash/fp/map.js").as_deref(),
+            Some("/vendor/lodash-fp/map.js")
+        );
+        assert_eq!(mapped(&imports, "react"), None);
+    }
+
+    #[test]
+    fn lookup_blocks_prefixes_without_trailing_slash() {
+        let imports = map(&[("a/", "/lib/a.js")]);
+
+        assert_eq!(lookup(&imports, "a/b.js"), Some(None));
+    }
+
+    #[test]
+    fn scopes_match_by_prefix_most_specific_first() {
+        let map = ImportMap::from_json(
+            r#"{
+                "imports": { "dep": "/dep/v1.js" },
+                "scopes": {
+                    "/app/": { "dep": "/dep/v2.js" },
+                    "/app/legacy/": { "dep": "/dep/v0.js" },
+                    "/main.js": { "dep": "/dep/main.js" }
+                }
+            }"#,
+        )
+        .unwrap();
+
+        let resolve = |base: &str| map.resolve(base, "dep").unwrap();
+
+        assert_eq!(resolve("/other.js").as_deref(), Some("/dep/v1.js"));
+        assert_eq!(resolve("/app/a.js").as_deref(), Some("/dep/v2.js"));
+        assert_eq!(resolve("/app/legacy/a.js").as_deref(), Some("/dep/v0.js"));
+        assert_eq!(resolve("/main.js").as_deref(), Some("/dep/main.js"));
+        assert_eq!(resolve("/main.jsx").as_deref(), Some("/dep/v1.js"));
+    }
+
+    #[test]
+    fn blocks_addresses_that_are_not_strings() {
+        let map = ImportMap::from_json(
+            r#"{
+                "imports": { "a": null, "b": 1, "c": "/c.js" },
+                "scopes": { "/app/": { "c": null } }
+            }"#,
+        )
+        .unwrap();
+
+        assert!(map.resolve("/main.js", "a").is_err());
+        assert!(map.resolve("/main.js", "b").is_err());
+        assert_eq!(
+            map.resolve("/main.js", "c").unwrap().as_deref(),
+            Some("/c.js")
+        );
+        assert!(map.resolve("/app/main.js", "c").is_err());
+        assert_eq!(map.resolve("/main.js", "d").unwrap(), None);
+    }
+}
@@ -1,11 +1,28 @@
 pub use quickjs_sys as sys;
 
+pub mod allocator;
+pub mod bytecode;
+pub mod cache;
+pub mod clone;
+pub mod console;
 pub mod context;
+pub mod embed;
 pub mod error;
 pub mod function;
+pub mod import_map;
+pub mod loader;
+pub mod memory;
 pub mod module;
+pub mod native;
+pub mod pool;
+pub mod require;
+pub mod resolver;
 pub mod runtime;
+pub mod send;
+pub mod template;
+pub mod timer;
 pub mod value;
+pub mod worker;
 
 fn main() {
     use crate::{context::Context, function::Function, module::Module, runtime::Runtime};
@@ -64,7 +81,7 @@ export function main(uint8, buffer, text) {
 }
 "#;
     let value = context.eval_module(script, "_main").unwrap();
-    let module = Module::new(value).unwrap();
+    let module = Module::new(value, true).unwrap();
 
     let value = module.get("main").unwrap();
     let function = Function::new(value).unwrap();
@@ -0,0 +1,167 @@
+use crate::{bytecode, context::Context, error::QuickError};
+use quickjs_sys as sys;
+use std::{collections::HashMap, fs, ptr::NonNull};
+
+pub struct ModuleDef(NonNull<sys::JSModuleDef>);
+
+impl ModuleDef {
+    /// # Safety
+    /// `ptr` must be a module created in the context passed to the loader.
+    pub unsafe fn from_raw(ptr: *mut sys::JSModuleDef) -> Option<Self> {
+        NonNull::new(ptr).map(ModuleDef)
+    }
+
+    pub fn as_ptr(&self) -> *mut sys::JSModuleDef {
+        self.0.as_ptr()
+    }
+}
+
+pub enum ModuleSource {
+    Source(String),
+    Bytecode(Vec<u8>),
+    Native(ModuleDef),
+}
+
+/// Supplies module sources. QuickJS requests them synchronously, `import()`
+/// included, so a loader cannot wait on the event loop for a source.
+pub trait ModuleLoader {
+    fn load(&self, ctx: &Context, name: &str) -> Result<ModuleSource, QuickError>;
+}
+
+#[derive(Default)]
+pub struct FileLoader;
+
+impl ModuleLoader for FileLoader {
+    fn load(&self, _: &Context, name: &str) -> Result<ModuleSource, QuickError> {
+        match fs::read_to_string(name) {
+            Ok(source) => Ok(ModuleSource::Source(source)),
+            Err(e) => Err(QuickError::ModuleError(format!("{name}: {e}"))),
+        }
+    }
+}
+
+#[derive(Clone)]
+enum Entry {
+    Source(String),
+    Bytecode(Vec<u8>),
+}
+
+#[derive(Clone, Default)]
+pub struct MemoryLoader {
+    modules: HashMap<String, Entry>,
+}
+
+impl MemoryLoader {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert_source(&mut self, name: impl Into<String>, source: impl Into<String>) {
+        self.modules
+            .insert(name.into(), Entry::Source(source.into()));
+    }
+
+    pub fn insert_bytecode(&mut self, name: impl Into<String>, bytecode: impl Into<Vec<u8>>) {
+        self.modules
+            .insert(name.into(), Entry::Bytecode(bytecode.into()));
+    }
+
+    pub fn remove(&mut self, name: impl AsRef<str>) -> bool {
+        self.modules.remove(name.as_ref()).is_some()
+    }
+
+    pub fn contains(&self, name: impl AsRef<str>) -> bool {
+        self.modules.contains_key(name.as_ref())
+    }
+}
+
+impl ModuleLoader for MemoryLoader {
+    fn load(&self, _: &Context, name: &str) -> Result<ModuleSource, QuickError> {
+        match self.modules.get(name) {
+            Some(Entry::Source(source)) => Ok(ModuleSource::Source(source.clone())),
+            Some(Entry::Bytecode(bytecode)) => Ok(ModuleSource::Bytecode(bytecode.clone())),
+            None => Err(QuickError::ModuleError(format!("{name}: not found"))),
+        }
+    }
+}
+
+/// Embeds the listed files into the binary, one `name => path` pair per
+/// module. Paths resolve like `include_bytes!`, relative to the calling file;
+/// use [`embed_dir!`](crate::embed_dir) for a whole directory.
+#[macro_export]
+macro_rules! embed_files {
+    ($($name:literal => $path:literal),* $(,)?) => {
+        $crate::loader::EmbeddedLoader::new(&[$(($name, &include_bytes!($path)[..])),*])
+    };
+}
+
+/// Serves modules compiled into the binary, keyed by their exact name. Build
+/// it with [`embed_files!`](crate::embed_files) or
+/// [`embed_dir!`](crate::embed_dir).
+pub struct EmbeddedLoader {
+    files: HashMap<&'static str, &'static [u8]>,
+}
+
+impl EmbeddedLoader {
+    pub fn new(files: &[(&'static str, &'static [u8])]) -> Self {
+        EmbeddedLoader {
+            files: files.iter().copied().collect(),
+        }
+    }
+}
+
+impl ModuleLoader for EmbeddedLoader {
+    fn load(&self, _: &Context, name: &str) -> Result<ModuleSource, QuickError> {
+        let file = match self.files.get(name) {
+            Some(v) => *v,
+            None => return Err(QuickError::ModuleError(format!("{name}: not found"))),
+        };
+
+        if bytecode::is_bytecode(file) {
+            return Ok(ModuleSource::Bytecode(file.to_vec()));
+        }
+
+        match std::str::from_utf8(file) {
+            Ok(source) => Ok(ModuleSource::Source(source.to_string())),
+            Err(e) => Err(QuickError::ModuleError(format!("{name}: {e}"))),
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct ChainLoader {
+    loaders: Vec<Box<dyn ModuleLoader>>,
+}
+
+impl ChainLoader {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with(mut self, loader: impl ModuleLoader + 'static) -> Self {
+        self.loaders.push(Box::new(loader));
+        self
+    }
+
+    pub fn push(&mut self, loader: Box<dyn ModuleLoader>) {
+        self.loaders.push(loader);
+    }
+}
+
+impl ModuleLoader for ChainLoader {
+    fn load(&self, ctx: &Context, name: &str) -> Result<ModuleSource, QuickError> {
+        let mut errors = Vec::with_capacity(self.loaders.len());
+
+        for loader in &self.loaders {
+            match loader.load(ctx, name) {
+                Ok(source) => return Ok(source),
+                Err(e) => errors.push(e.to_string()),
+            }
+        }
+
+        Err(QuickError::ModuleError(format!(
+            "{name}: [{}]",
+            errors.join(", ")
+        )))
+    }
+}
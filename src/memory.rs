@@ -0,0 +1,121 @@
+use quickjs_sys as sys;
+use serde::Serialize;
+use std::fmt;
+
+#[derive(Serialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MemoryUsage {
+    pub malloc_size: i64,
+    pub malloc_limit: i64,
+    pub memory_used_size: i64,
+    pub malloc_count: i64,
+    pub memory_used_count: i64,
+    pub atom_count: i64,
+    pub atom_size: i64,
+    pub str_count: i64,
+    pub str_size: i64,
+    pub obj_count: i64,
+    pub obj_size: i64,
+    pub prop_count: i64,
+    pub prop_size: i64,
+    pub shape_count: i64,
+    pub shape_size: i64,
+    pub js_func_count: i64,
+    pub js_func_size: i64,
+    pub js_func_code_size: i64,
+    pub js_func_pc2line_count: i64,
+    pub js_func_pc2line_size: i64,
+    pub c_func_count: i64,
+    pub array_count: i64,
+    pub fast_array_count: i64,
+    pub fast_array_elements: i64,
+    pub binary_object_count: i64,
+    pub binary_object_size: i64,
+}
+
+impl From<sys::JSMemoryUsage> for MemoryUsage {
+    fn from(value: sys::JSMemoryUsage) -> Self {
+        MemoryUsage {
+            malloc_size: value.malloc_size,
+            malloc_limit: value.malloc_limit,
+            memory_used_size: value.memory_used_size,
+            malloc_count: value.malloc_count,
+            memory_used_count: value.memory_used_count,
+            atom_count: value.atom_count,
+            atom_size: value.atom_size,
+            str_count: value.str_count,
+            str_size: value.str_size,
+            obj_count: value.obj_count,
+            obj_size: value.obj_size,
+            prop_count: value.prop_count,
+            prop_size: value.prop_size,
+            shape_count: value.shape_count,
+            shape_size: value.shape_size,
+            js_func_count: value.js_func_count,
+            js_func_size: value.js_func_size,
+            js_func_code_size: value.js_func_code_size,
+            js_func_pc2line_count: value.js_func_pc2line_count,
+            js_func_pc2line_size: value.js_func_pc2line_size,
+            c_func_count: value.c_func_count,
+            array_count: value.array_count,
+            fast_array_count: value.fast_array_count,
+            fast_array_elements: value.fast_array_elements,
+            binary_object_count: value.binary_object_count,
+            binary_object_size: value.binary_object_size,
+        }
+    }
+}
+
+impl fmt::Display for MemoryUsage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fn row(f: &mut fmt::Formatter<'_>, name: &str, count: i64, size: i64) -> fmt::Result {
+            let average = if count > 0 {
+                format!("({:.1} per block)", size as f64 / count as f64)
+            } else {
+                String::new()
+            };
+
+            writeln!(f, "{name:<24}{count:>10}{size:>12}  {average}")
+        }
+
+        if self.malloc_limit > 0 {
+            writeln!(f, "memory limit: {}", self.malloc_limit)?;
+        }
+
+        writeln!(f, "{:<24}{:>10}{:>12}", "NAME", "COUNT", "SIZE")?;
+        row(f, "memory allocated", self.malloc_count, self.malloc_size)?;
+        row(
+            f,
+            "memory used",
+            self.memory_used_count,
+            self.memory_used_size,
+        )?;
+        row(f, "atoms", self.atom_count, self.atom_size)?;
+        row(f, "strings", self.str_count, self.str_size)?;
+        row(f, "objects", self.obj_count, self.obj_size)?;
+        row(f, "properties", self.prop_count, self.prop_size)?;
+        row(f, "shapes", self.shape_count, self.shape_size)?;
+        row(
+            f,
+            "bytecode functions",
+            self.js_func_count,
+            self.js_func_size,
+        )?;
+        row(f, "bytecode", self.js_func_count, self.js_func_code_size)?;
+        row(
+            f,
+            "pc2line",
+            self.js_func_pc2line_count,
+            self.js_func_pc2line_size,
+        )?;
+        row(f, "C functions", self.c_func_count, 0)?;
+        row(f, "arrays", self.array_count, 0)?;
+        row(f, "fast arrays", self.fast_array_count, 0)?;
+        row(f, "elements", self.fast_array_elements, 0)?;
+        row(
+            f,
+            "binary objects",
+            self.binary_object_count,
+            self.binary_object_size,
+        )
+    }
+}
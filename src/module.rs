@@ -1,9 +1,17 @@
 use crate::{
+    context::Context,
     error::QuickError,
+    function::{self, Function},
+    runtime::{self, State},
     value::{Exception, JSValueRef},
 };
+use log::warn;
 use quickjs_sys as sys;
-use std::ffi::{c_char, CString};
+use std::{
+    ffi::{c_char, CStr, CString},
+    fs,
+    mem::ManuallyDrop,
+};
 
 extern "C" {
     fn JS_GetModuleExport_real(
@@ -15,23 +23,122 @@ extern "C" {
 
 pub struct Module {
     value: JSValueRef,
+    promise: Option<JSValueRef>,
 }
 
 impl Module {
-    pub fn new(value: JSValueRef) -> Result<Self, QuickError> {
-        let _value = unsafe { sys::JS_EvalFunction(value.ctx, value.clone().val()) };
-        let _value = JSValueRef::from_value(value.ctx, _value);
+    /// Links and evaluates the module, then runs queued jobs until it settles
+    /// or the queue is empty. A module still waiting on top-level `await` is
+    /// returned as is; drive [`Module::promise`] with
+    /// [`Context::await_promise`] or the event loop.
+    ///
+    /// `main` sets `import.meta.main` and should only be true for the entry
+    /// module.
+    pub fn new(value: JSValueRef, main: bool) -> Result<Self, QuickError> {
+        let mut module = Self::from_value(value, main)?;
+        module.link()?;
+
+        let promise = module.evaluate()?;
+        let rt = unsafe { sys::JS_GetRuntime(promise.ctx) };
+
+        // Only run jobs while this module is pending. Jobs queued by other
+        // modules or contexts may fail, but that is not this module's error.
+        let pending = || unsafe {
+            sys::JS_PromiseState(promise.ctx, promise.val)
+                == sys::JSPromiseStateEnum_JS_PROMISE_PENDING
+        };
+        while pending() {
+            match runtime::execute_pending_job(rt) {
+                Ok(true) => {}
+                Ok(false) => break,
+                Err(e) => warn!("{e}"),
+            }
+        }
+
+        let state = unsafe { sys::JS_PromiseState(promise.ctx, promise.val) };
+        if state == sys::JSPromiseStateEnum_JS_PROMISE_REJECTED {
+            let value = unsafe { sys::JS_PromiseResult(promise.ctx, promise.val) };
+            let value = JSValueRef::from_value(promise.ctx, value);
+
+            return Err(Exception(value).into_error(QuickError::EvalError));
+        }
+
+        module.promise = Some(promise);
+        Ok(module)
+    }
+
+    /// The promise returned by the module's evaluation, if it has run.
+    pub fn promise(&self) -> Option<JSValueRef> {
+        self.promise.clone()
+    }
+
+    pub fn compile(
+        ctx: &Context,
+        source: impl AsRef<str>,
+        name: impl AsRef<str>,
+        main: bool,
+    ) -> Result<Self, QuickError> {
+        let value = ctx.eval_module(source, name).map_err(|e| match e {
+            QuickError::EvalError(e) => QuickError::CodeError(e),
+            e => e,
+        })?;
+
+        Self::from_value(value, main)
+    }
+
+    pub fn from_value(value: JSValueRef, main: bool) -> Result<Self, QuickError> {
+        if value.tag() != sys::JS_TAG_MODULE {
+            return Err(QuickError::UnsupportedTypeError(value.tag()));
+        }
+
+        let module = Module {
+            value,
+            promise: None,
+        };
+
+        let ctx = ManuallyDrop::new(Context(module.value.ctx));
+        init_import_meta(&ctx, module.def(), &module.name()?, main)?;
+
+        Ok(module)
+    }
+
+    pub fn name(&self) -> Result<String, QuickError> {
+        module_name(self.value.ctx, self.def())
+    }
 
-        if _value.tag() == sys::JS_TAG_EXCEPTION {
-            let exception = unsafe { sys::JS_GetException(value.ctx) };
-            let exception = JSValueRef::from_value(value.ctx, exception);
+    pub fn import_meta(&self) -> Result<JSValueRef, QuickError> {
+        import_meta(self.value.ctx, self.def())
+    }
 
-            Err(QuickError::EvalError(Exception(exception).to_string()))
+    pub fn link(&self) -> Result<(), QuickError> {
+        if unsafe { sys::JS_ResolveModule(self.value.ctx, self.value.val) } < 0 {
+            let value = unsafe { sys::JS_GetException(self.value.ctx) };
+            let value = JSValueRef::from_value(self.value.ctx, value);
+
+            Err(Exception(value).into_error(QuickError::LinkError))
         } else {
-            Ok(Module { value })
+            Ok(())
         }
     }
 
+    pub fn evaluate(&self) -> Result<JSValueRef, QuickError> {
+        let value = unsafe { sys::JS_EvalFunction(self.value.ctx, self.value.clone().val()) };
+        let value = JSValueRef::from_value(self.value.ctx, value);
+
+        if value.tag() == sys::JS_TAG_EXCEPTION {
+            let value = unsafe { sys::JS_GetException(self.value.ctx) };
+            let value = JSValueRef::from_value(self.value.ctx, value);
+
+            Err(Exception(value).into_error(QuickError::EvalError))
+        } else {
+            Ok(value)
+        }
+    }
+
+    fn def(&self) -> *mut sys::JSModuleDef {
+        self.value.ptr() as *mut sys::JSModuleDef
+    }
+
     pub fn get(&self, name: impl AsRef<str>) -> Result<JSValueRef, QuickError> {
         let c_name = match CString::new(name.as_ref()) {
             Ok(c_name) => c_name,
@@ -51,9 +158,109 @@ impl Module {
             let value = unsafe { sys::JS_GetException(self.value.ctx) };
             let value = JSValueRef::from_value(self.value.ctx, value);
 
-            Err(QuickError::EvalError(Exception(value).to_string()))
+            Err(Exception(value).into_error(QuickError::EvalError))
         } else {
             Ok(value)
         }
     }
+
+    pub fn namespace(&self) -> Result<JSValueRef, QuickError> {
+        let value = unsafe {
+            sys::JS_GetModuleNamespace(self.value.ctx, self.value.ptr() as *mut sys::JSModuleDef)
+        };
+        let value = JSValueRef::from_value(self.value.ctx, value);
+
+        if value.tag() == sys::JS_TAG_EXCEPTION {
+            let value = unsafe { sys::JS_GetException(self.value.ctx) };
+            let value = JSValueRef::from_value(self.value.ctx, value);
+
+            Err(Exception(value).into_error(QuickError::EvalError))
+        } else {
+            Ok(value)
+        }
+    }
+
+    pub fn exports(&self) -> Result<Vec<String>, QuickError> {
+        self.namespace()?.keys()
+    }
+
+    pub fn default_export(&self) -> Result<JSValueRef, QuickError> {
+        self.get("default")
+    }
+
+    pub fn get_function(&self, name: impl AsRef<str>) -> Result<Function, QuickError> {
+        let value = self.get(name)?;
+
+        if !value.is_function() {
+            return Err(QuickError::UnsupportedTypeError(value.tag()));
+        }
+
+        function::wrap(value)
+    }
+}
+
+pub type ImportMetaHook = dyn Fn(&Context, &str, &JSValueRef) -> Result<(), QuickError>;
+
+pub(crate) fn module_name(
+    ctx: *mut sys::JSContext,
+    module: *mut sys::JSModuleDef,
+) -> Result<String, QuickError> {
+    unsafe {
+        let atom = sys::JS_GetModuleName(ctx, module);
+        let name = sys::JS_AtomToCString(ctx, atom);
+        sys::JS_FreeAtom(ctx, atom);
+
+        if name.is_null() {
+            let value = sys::JS_GetException(ctx);
+            let value = JSValueRef::from_value(ctx, value);
+
+            return Err(Exception(value).into_error(QuickError::ModuleError));
+        }
+
+        let string = CStr::from_ptr(name).to_string_lossy().to_string();
+        sys::JS_FreeCString(ctx, name);
+
+        Ok(string)
+    }
+}
+
+fn import_meta(
+    ctx: *mut sys::JSContext,
+    module: *mut sys::JSModuleDef,
+) -> Result<JSValueRef, QuickError> {
+    let value = unsafe { sys::JS_GetImportMeta(ctx, module) };
+    let value = JSValueRef::from_value(ctx, value);
+
+    if value.tag() == sys::JS_TAG_EXCEPTION {
+        let value = unsafe { sys::JS_GetException(ctx) };
+        let value = JSValueRef::from_value(ctx, value);
+
+        Err(Exception(value).into_error(QuickError::ModuleError))
+    } else {
+        Ok(value)
+    }
+}
+
+pub(crate) fn init_import_meta(
+    ctx: &Context,
+    module: *mut sys::JSModuleDef,
+    name: &str,
+    main: bool,
+) -> Result<(), QuickError> {
+    let meta = import_meta(ctx.0, module)?;
+
+    let url = match fs::canonicalize(name) {
+        Ok(path) => format!("file://{}", path.display()),
+        Err(_) => name.to_string(),
+    };
+
+    meta.set_property("url", ctx.make_string(url)?)?;
+    meta.set_property("path", ctx.make_string(name)?)?;
+    meta.set_property("main", ctx.make_bool(main))?;
+
+    let state = unsafe { State::from_context(ctx.0) };
+    match state.import_meta.borrow().as_ref() {
+        Some(hook) => hook(ctx, name, &meta),
+        None => Ok(()),
+    }
 }
@@ -0,0 +1,140 @@
+use crate::{
+    context::Context,
+    error::QuickError,
+    loader::ModuleDef,
+    runtime::State,
+    value::{Exception, JSValueRef},
+};
+use log::error;
+use quickjs_sys as sys;
+use std::{
+    collections::HashMap,
+    ffi::{c_int, CString},
+    mem::ManuallyDrop,
+    rc::Rc,
+};
+
+type NativeFunction = dyn Fn(ManuallyDrop<Context>, Vec<ManuallyDrop<JSValueRef>>) -> JSValueRef;
+
+#[derive(Clone)]
+enum Export {
+    Function(i32, Rc<NativeFunction>),
+    Value(Rc<dyn Fn(&Context) -> JSValueRef>),
+}
+
+#[derive(Default)]
+pub(crate) struct Registry {
+    pub(crate) modules: HashMap<String, ModuleBuilder>,
+    pub(crate) pending: HashMap<usize, ModuleBuilder>,
+}
+
+#[derive(Clone, Default)]
+pub struct ModuleBuilder {
+    exports: Vec<(CString, Export)>,
+}
+
+impl ModuleBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn function<F>(self, name: impl AsRef<str>, args: i32, value: F) -> Result<Self, QuickError>
+    where
+        F: Fn(ManuallyDrop<Context>, Vec<ManuallyDrop<JSValueRef>>) -> JSValueRef + 'static,
+    {
+        self.export(name, Export::Function(args, Rc::new(value)))
+    }
+
+    pub fn value<F>(self, name: impl AsRef<str>, value: F) -> Result<Self, QuickError>
+    where
+        F: Fn(&Context) -> JSValueRef + 'static,
+    {
+        self.export(name, Export::Value(Rc::new(value)))
+    }
+
+    pub fn exports(&self) -> impl Iterator<Item = &str> {
+        self.exports
+            .iter()
+            .filter_map(|(name, _)| name.to_str().ok())
+    }
+
+    pub fn build(&self, ctx: &Context, name: impl AsRef<str>) -> Result<ModuleDef, QuickError> {
+        let c_name = match CString::new(name.as_ref()) {
+            Ok(v) => v,
+            Err(e) => return Err(QuickError::CStringError(e.to_string())),
+        };
+
+        unsafe {
+            let module = sys::JS_NewCModule(ctx.0, c_name.as_ptr(), Some(init));
+            let module = match ModuleDef::from_raw(module) {
+                Some(v) => v,
+                None => return Err(exception(ctx)),
+            };
+
+            for (name, _) in &self.exports {
+                if sys::JS_AddModuleExport(ctx.0, module.as_ptr(), name.as_ptr()) < 0 {
+                    return Err(exception(ctx));
+                }
+            }
+
+            State::from_context(ctx.0)
+                .native
+                .borrow_mut()
+                .pending
+                .insert(module.as_ptr() as usize, self.clone());
+
+            Ok(module)
+        }
+    }
+
+    fn export(mut self, name: impl AsRef<str>, export: Export) -> Result<Self, QuickError> {
+        let name = match CString::new(name.as_ref()) {
+            Ok(v) => v,
+            Err(e) => return Err(QuickError::CStringError(e.to_string())),
+        };
+
+        self.exports.push((name, export));
+        Ok(self)
+    }
+
+    fn init(&self, ctx: &Context, module: *mut sys::JSModuleDef) -> c_int {
+        for (name, export) in &self.exports {
+            let value = match export {
+                Export::Function(args, value) => {
+                    let value = value.clone();
+                    ctx.new_function(*args, move |ctx, args| value(ctx, args))
+                }
+                Export::Value(value) => value(ctx),
+            };
+
+            if unsafe { sys::JS_SetModuleExport(ctx.0, module, name.as_ptr(), value.val()) } < 0 {
+                return -1;
+            }
+        }
+
+        0
+    }
+}
+
+unsafe extern "C" fn init(ctx: *mut sys::JSContext, module: *mut sys::JSModuleDef) -> c_int {
+    let builder = State::from_context(ctx)
+        .native
+        .borrow_mut()
+        .pending
+        .remove(&(module as usize));
+
+    match builder {
+        Some(builder) => builder.init(&ManuallyDrop::new(Context(ctx)), module),
+        None => {
+            error!("native module initialized without a builder");
+            -1
+        }
+    }
+}
+
+fn exception(ctx: &Context) -> QuickError {
+    let value = unsafe { sys::JS_GetException(ctx.0) };
+    let value = JSValueRef::from_value(ctx.0, value);
+
+    Exception(value).into_error(QuickError::ModuleError)
+}
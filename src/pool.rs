@@ -0,0 +1,280 @@
+use crate::{
+    context::{Context, ContextBuilder},
+    error::QuickError,
+    loader::{ChainLoader, FileLoader, MemoryLoader},
+    module::Module,
+    runtime::{Runtime, RuntimeBuilder},
+};
+use log::error;
+use quickjs_sys as sys;
+use std::{
+    panic::{self, AssertUnwindSafe},
+    sync::{mpsc, Arc, Mutex},
+    thread::{self, JoinHandle},
+};
+
+type Job = Box<dyn FnOnce(Result<&Context, QuickError>) + Send>;
+type Setup = dyn Fn(&Runtime, &Context) -> Result<(), QuickError> + Send + Sync;
+
+struct Config {
+    runtime: RuntimeBuilder,
+    context: ContextBuilder,
+    max_uses: Option<usize>,
+    max_growth: Option<usize>,
+    modules: Vec<(String, Vec<u8>)>,
+    setup: Option<Box<Setup>>,
+}
+
+pub struct RuntimePoolBuilder {
+    threads: usize,
+    config: Config,
+}
+
+impl RuntimePoolBuilder {
+    pub fn new() -> Self {
+        RuntimePoolBuilder {
+            threads: thread::available_parallelism().map_or(1, |v| v.get()),
+            config: Config {
+                runtime: RuntimeBuilder::new(),
+                context: ContextBuilder::new(),
+                max_uses: None,
+                max_growth: None,
+                modules: Vec::new(),
+                setup: None,
+            },
+        }
+    }
+
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.threads = threads.max(1);
+        self
+    }
+
+    pub fn runtime(mut self, builder: RuntimeBuilder) -> Self {
+        self.config.runtime = builder;
+        self
+    }
+
+    pub fn context(mut self, builder: ContextBuilder) -> Self {
+        self.config.context = builder;
+        self
+    }
+
+    /// Replaces a worker's context after it has served `uses` jobs.
+    pub fn max_uses(mut self, uses: usize) -> Self {
+        self.config.max_uses = Some(uses);
+        self
+    }
+
+    /// Replaces a worker's context once its runtime has grown by more than
+    /// `bytes` since the context was created.
+    pub fn max_memory_growth(mut self, bytes: usize) -> Self {
+        self.config.max_growth = Some(bytes);
+        self
+    }
+
+    /// Compiles `source` once and evaluates it in every fresh context.
+    pub fn module(
+        self,
+        name: impl Into<String>,
+        source: impl AsRef<str>,
+    ) -> Result<Self, QuickError> {
+        let name = name.into();
+
+        let runtime = Runtime::default();
+        let context = Context::from(&runtime);
+        let bytecode = context.compile_to_bytecode(source, &name)?;
+        drop(context);
+
+        Ok(self.bytecode(name, bytecode))
+    }
+
+    pub fn bytecode(mut self, name: impl Into<String>, bytecode: impl Into<Vec<u8>>) -> Self {
+        self.config.modules.push((name.into(), bytecode.into()));
+        self
+    }
+
+    /// Runs after the preloaded modules on every fresh context.
+    pub fn setup<F>(mut self, setup: F) -> Self
+    where
+        F: Fn(&Runtime, &Context) -> Result<(), QuickError> + Send + Sync + 'static,
+    {
+        self.config.setup = Some(Box::new(setup));
+        self
+    }
+
+    pub fn build(self) -> Result<RuntimePool, QuickError> {
+        let config = Arc::new(self.config);
+        let (sender, receiver) = mpsc::channel::<Job>();
+        let receiver = Arc::new(Mutex::new(receiver));
+
+        let mut workers = Vec::with_capacity(self.threads);
+        for index in 0..self.threads {
+            let config = config.clone();
+            let receiver = receiver.clone();
+
+            let worker = thread::Builder::new()
+                .name(format!("quickjs-pool-{index}"))
+                .spawn(move || work(&config, &receiver))
+                .map_err(|e| QuickError::PoolError(e.to_string()))?;
+            workers.push(worker);
+        }
+
+        Ok(RuntimePool {
+            sender: Some(sender),
+            workers,
+        })
+    }
+}
+
+impl Default for RuntimePoolBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub struct RuntimePool {
+    sender: Option<mpsc::Sender<Job>>,
+    workers: Vec<JoinHandle<()>>,
+}
+
+impl RuntimePool {
+    pub fn new(threads: usize) -> Result<Self, QuickError> {
+        RuntimePoolBuilder::new().threads(threads).build()
+    }
+
+    pub fn builder() -> RuntimePoolBuilder {
+        RuntimePoolBuilder::new()
+    }
+
+    pub fn size(&self) -> usize {
+        self.workers.len()
+    }
+
+    /// Queues `job` on the next idle runtime. The result, or the error that
+    /// kept the worker from preparing a context, arrives on the returned
+    /// channel, which is closed without a value if the job panics.
+    pub fn spawn<F, R>(&self, job: F) -> Result<mpsc::Receiver<Result<R, QuickError>>, QuickError>
+    where
+        F: FnOnce(&Context) -> R + Send + 'static,
+        R: Send + 'static,
+    {
+        let (sender, receiver) = mpsc::sync_channel(1);
+        let job: Job = Box::new(move |ctx| {
+            let _ = sender.send(ctx.map(job));
+        });
+
+        match &self.sender {
+            Some(v) => v
+                .send(job)
+                .map_err(|_| QuickError::PoolError(String::from("pool is shut down")))?,
+            None => return Err(QuickError::PoolError(String::from("pool is shut down"))),
+        }
+
+        Ok(receiver)
+    }
+
+    pub fn run<F, R>(&self, job: F) -> Result<R, QuickError>
+    where
+        F: FnOnce(&Context) -> R + Send + 'static,
+        R: Send + 'static,
+    {
+        self.spawn(job)?
+            .recv()
+            .map_err(|_| QuickError::PoolError(String::from("job did not complete")))?
+    }
+}
+
+impl Drop for RuntimePool {
+    fn drop(&mut self) {
+        drop(self.sender.take());
+
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
+struct Slot {
+    context: Context,
+    uses: usize,
+    baseline: i64,
+}
+
+fn work(config: &Config, receiver: &Mutex<mpsc::Receiver<Job>>) {
+    let runtime = match config.runtime.build() {
+        Ok(v) => v,
+        Err(e) => {
+            error!("pool: {e}");
+            while let Some(job) = next(receiver) {
+                job(Err(QuickError::PoolError(e.to_string())));
+            }
+            return;
+        }
+    };
+
+    let mut modules = MemoryLoader::new();
+    for (name, bytecode) in &config.modules {
+        modules.insert_bytecode(name.clone(), bytecode.clone());
+    }
+    runtime.set_loader(Box::new(ChainLoader::new().with(modules).with(FileLoader)));
+
+    let mut slot: Option<Slot> = None;
+
+    while let Some(job) = next(receiver) {
+        let mut current = match slot.take() {
+            Some(v) => v,
+            None => match prepare(config, &runtime) {
+                Ok(v) => v,
+                Err(e) => {
+                    job(Err(e));
+                    continue;
+                }
+            },
+        };
+
+        let ok = panic::catch_unwind(AssertUnwindSafe(|| job(Ok(&current.context)))).is_ok();
+        current.uses += 1;
+
+        let growth = runtime.memory_usage().memory_used_size - current.baseline;
+        let recycle = !ok
+            || config.max_uses.is_some_and(|v| current.uses >= v)
+            || config.max_growth.is_some_and(|v| growth > v as i64);
+
+        if recycle {
+            drop(current);
+            runtime.gc();
+        } else {
+            slot = Some(current);
+        }
+    }
+
+    drop(slot);
+}
+
+fn next(receiver: &Mutex<mpsc::Receiver<Job>>) -> Option<Job> {
+    receiver.lock().ok()?.recv().ok()
+}
+
+fn prepare(config: &Config, runtime: &Runtime) -> Result<Slot, QuickError> {
+    let context = config.context.build(runtime)?;
+
+    for (_, bytecode) in &config.modules {
+        let value = context.load_bytecode(bytecode)?;
+        if value.tag() == sys::JS_TAG_MODULE {
+            Module::new(value, false)?;
+        }
+    }
+
+    if let Some(setup) = &config.setup {
+        setup(runtime, &context)?;
+    }
+
+    runtime.run_pending_jobs()?;
+
+    Ok(Slot {
+        context,
+        uses: 0,
+        baseline: runtime.memory_usage().memory_used_size,
+    })
+}
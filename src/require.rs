@@ -0,0 +1,106 @@
+use crate::{context::Context, error::QuickError, function, loader::ModuleSource, runtime::State};
+
+const PRELUDE: &str = r#"
+(function (resolve, load, compile) {
+    const cache = Object.create(null);
+
+    function dirname(path) {
+        const index = path.lastIndexOf("/");
+        return index < 0 ? "." : path.slice(0, index) || "/";
+    }
+
+    function make(base) {
+        function require(name) {
+            const path = resolve(base, name);
+            if (path in cache) {
+                return cache[path].exports;
+            }
+
+            const module = { id: path, filename: path, exports: {}, loaded: false };
+            cache[path] = module;
+
+            try {
+                const source = load(path);
+
+                if (path.endsWith(".json")) {
+                    module.exports = JSON.parse(source);
+                } else {
+                    const wrapper = compile(path, source);
+                    wrapper.call(module.exports, module.exports, make(path), module, path, dirname(path));
+                }
+            } catch (e) {
+                delete cache[path];
+                throw e;
+            }
+
+            module.loaded = true;
+            return module.exports;
+        }
+
+        require.cache = cache;
+        require.resolve = (name) => resolve(base, name);
+
+        return require;
+    }
+
+    return make;
+})
+"#;
+
+pub(crate) fn install(ctx: &Context) -> Result<(), QuickError> {
+    let resolve = ctx.new_function(2, |ctx, args| {
+        let (base, name) = match (args[0].to_string(), args[1].to_string()) {
+            (Ok(a), Ok(b)) => (a, b),
+            _ => return ctx.throw_error("require: expected a string"),
+        };
+
+        let state = unsafe { State::from_context(ctx.0) };
+        let path = state.resolver.borrow().resolve_require(&ctx, &base, &name);
+
+        match path.and_then(|v| ctx.make_string(v)) {
+            Ok(v) => v,
+            Err(e) => ctx.throw_error(format!("Cannot find module '{name}': {e}")),
+        }
+    });
+
+    let load = ctx.new_function(1, |ctx, args| {
+        let path = match args[0].to_string() {
+            Ok(v) => v,
+            Err(e) => return ctx.throw_error(e.to_string()),
+        };
+
+        let state = unsafe { State::from_context(ctx.0) };
+        let source = state.loader.borrow().load(&ctx, &path);
+
+        match source {
+            Ok(ModuleSource::Source(source)) => match ctx.make_string(source) {
+                Ok(v) => v,
+                Err(e) => ctx.throw_error(e.to_string()),
+            },
+            Ok(_) => ctx.throw_error(format!("Cannot require '{path}': not a source file")),
+            Err(e) => ctx.throw_error(format!("Cannot find module '{path}': {e}")),
+        }
+    });
+
+    let compile = ctx.new_function(2, |ctx, args| {
+        let (path, source) = match (args[0].to_string(), args[1].to_string()) {
+            (Ok(a), Ok(b)) => (a, b),
+            _ => return ctx.throw_error("require: expected a string"),
+        };
+
+        let source = format!(
+            "(function (exports, require, module, __filename, __dirname) {{{source}\n}})\n"
+        );
+
+        match ctx.eval_global(source, path) {
+            Ok(v) => v,
+            Err(e) => ctx.throw_error(e.to_string()),
+        }
+    });
+
+    let prelude = ctx.eval_global(PRELUDE, "<require>")?;
+    let make = function::call(prelude, None, vec![resolve, load, compile])?;
+    let require = function::call(make, None, vec![ctx.make_string("")?])?;
+
+    ctx.global().set_property("require", require)
+}
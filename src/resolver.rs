@@ -0,0 +1,236 @@
+use crate::{context::Context, error::QuickError};
+use std::path::{Component, Path, PathBuf};
+
+pub trait ModuleResolver {
+    fn resolve(&self, ctx: &Context, base: &str, name: &str) -> Result<String, QuickError>;
+
+    /// Resolves a CommonJS `require` specifier, which may also name JSON.
+    fn resolve_require(&self, ctx: &Context, base: &str, name: &str) -> Result<String, QuickError> {
+        self.resolve(ctx, base, name)
+    }
+}
+
+pub struct FileResolver {
+    root: Option<PathBuf>,
+    extensions: Vec<String>,
+}
+
+impl FileResolver {
+    pub fn new(root: Option<PathBuf>) -> Self {
+        FileResolver {
+            root,
+            extensions: vec![String::from(".js"), String::from(".mjs")],
+        }
+    }
+
+    pub fn root(&self) -> Option<&Path> {
+        self.root.as_deref()
+    }
+
+    pub fn set_root(&mut self, root: Option<PathBuf>) {
+        self.root = root;
+    }
+
+    pub fn set_extensions<I, S>(&mut self, extensions: I)
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.extensions = extensions.into_iter().map(Into::into).collect();
+    }
+
+    /// Resolves `name` against `base`. When a root is set, every relative or
+    /// `/` specifier must stay inside it after normalizing; bare names are
+    /// passed through unchanged.
+    pub fn resolve_path(&self, base: &str, name: &str) -> Result<String, QuickError> {
+        self.resolve_with(base, name, &[])
+    }
+
+    /// Like [`FileResolver::resolve_path`], but also probes `.json` as Node
+    /// does for `require`.
+    pub fn resolve_require_path(&self, base: &str, name: &str) -> Result<String, QuickError> {
+        self.resolve_with(base, name, &[".json"])
+    }
+
+    fn resolve_with(&self, base: &str, name: &str, extra: &[&str]) -> Result<String, QuickError> {
+        let path = if is_relative(name) {
+            let dir = Path::new(base).parent().unwrap_or(Path::new(""));
+
+            if dir.as_os_str().is_empty() {
+                self.root.as_deref().unwrap_or(Path::new("")).join(name)
+            } else {
+                dir.join(name)
+            }
+        } else if let Some(name) = name.strip_prefix('/') {
+            match &self.root {
+                Some(root) => root.join(name),
+                None => PathBuf::from("/").join(name),
+            }
+        } else {
+            return Ok(name.to_string());
+        };
+
+        let path = normalize(&path);
+
+        if let Some(root) = &self.root {
+            let inside = path
+                .strip_prefix(normalize(root))
+                .is_ok_and(|rest| !rest.components().any(|v| v == Component::ParentDir));
+
+            if !inside {
+                return Err(QuickError::ModuleError(format!(
+                    "{name}: resolves outside of the root {root:?}"
+                )));
+            }
+        }
+
+        Ok(self.probe(path, extra).to_string_lossy().to_string())
+    }
+
+    fn probe(&self, path: PathBuf, extra: &[&str]) -> PathBuf {
+        if path.is_file() {
+            return path;
+        }
+
+        let extensions = || {
+            self.extensions
+                .iter()
+                .map(String::as_str)
+                .chain(extra.iter().copied())
+        };
+
+        for extension in extensions() {
+            let mut candidate = path.clone().into_os_string();
+            candidate.push(extension);
+
+            let candidate = PathBuf::from(candidate);
+            if candidate.is_file() {
+                return candidate;
+            }
+        }
+
+        for extension in extensions() {
+            let candidate = path.join(format!("index{extension}"));
+            if candidate.is_file() {
+                return candidate;
+            }
+        }
+
+        path
+    }
+}
+
+impl ModuleResolver for FileResolver {
+    fn resolve(&self, _: &Context, base: &str, name: &str) -> Result<String, QuickError> {
+        self.resolve_path(base, name)
+    }
+
+    fn resolve_require(&self, _: &Context, base: &str, name: &str) -> Result<String, QuickError> {
+        self.resolve_require_path(base, name)
+    }
+}
+
+impl Default for FileResolver {
+    fn default() -> Self {
+        Self::new(None)
+    }
+}
+
+pub fn is_relative(name: &str) -> bool {
+    name == "." || name == ".." || name.starts_with("./") || name.starts_with("../")
+}
+
+pub fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => match normalized.components().next_back() {
+                Some(Component::Normal(_)) => {
+                    normalized.pop();
+                }
+                Some(Component::RootDir) | Some(Component::Prefix(_)) => {}
+                _ => normalized.push(".."),
+            },
+            component => normalized.push(component.as_os_str()),
+        }
+    }
+
+    normalized
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn normalized(path: &str) -> PathBuf {
+        normalize(Path::new(path))
+    }
+
+    #[test]
+    fn normalize_removes_dot_segments() {
+        assert_eq!(normalized("a/./b/../c"), PathBuf::from("a/c"));
+        assert_eq!(normalized("./a/b/.."), PathBuf::from("a"));
+        assert_eq!(normalized("/a/../../b"), PathBuf::from("/b"));
+    }
+
+    #[test]
+    fn normalize_keeps_leading_parent_segments() {
+        assert_eq!(normalized("../a"), PathBuf::from("../a"));
+        assert_eq!(normalized("a/../../b"), PathBuf::from("../b"));
+        assert_eq!(normalized("../../a/.."), PathBuf::from("../.."));
+    }
+
+    #[test]
+    fn specifiers_stay_inside_the_root() {
+        let resolver = FileResolver::new(Some(PathBuf::from("/srv/app")));
+
+        assert_eq!(
+            resolver.resolve_path("", "/lib/../util").unwrap(),
+            "/srv/app/util"
+        );
+        assert_eq!(
+            resolver
+                .resolve_path("/srv/app/lib/a.js", "../util")
+                .unwrap(),
+            "/srv/app/util"
+        );
+        assert!(resolver.resolve_path("", "/../etc/passwd").is_err());
+        assert!(resolver.resolve_path("", "../etc/passwd").is_err());
+        assert!(resolver
+            .resolve_path("/srv/app/main.js", "../../etc/passwd")
+            .is_err());
+        assert!(resolver
+            .resolve_path("/srv/app/main.js", "../other")
+            .is_err());
+    }
+
+    #[test]
+    fn specifiers_are_unrestricted_without_a_root() {
+        let resolver = FileResolver::default();
+
+        assert_eq!(
+            resolver
+                .resolve_path("/srv/app/main.js", "../../etc/passwd")
+                .unwrap(),
+            "/etc/passwd"
+        );
+        assert_eq!(resolver.resolve_path("", "lodash").unwrap(), "lodash");
+    }
+
+    #[test]
+    fn require_probes_json() {
+        let dir = std::env::temp_dir().join(format!("quick-rs-require-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("data.json"), "{}").unwrap();
+
+        let resolver = FileResolver::new(Some(dir.clone()));
+        let json = dir.join("data.json").to_string_lossy().to_string();
+
+        assert_eq!(resolver.resolve_require_path("", "./data").unwrap(), json);
+        assert_ne!(resolver.resolve_path("", "./data").unwrap(), json);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -1,95 +1,516 @@
-use crate::context::Context;
-use log::error;
+use crate::{
+    allocator::{self, Accounting, AllocatorStats, JsAllocator},
+    cache::BytecodeCache,
+    context::Context,
+    error::QuickError,
+    import_map::ImportMap,
+    loader::{FileLoader, ModuleLoader, ModuleSource},
+    memory::MemoryUsage,
+    module::{self, ImportMetaHook},
+    native::{ModuleBuilder, Registry},
+    resolver::{FileResolver, ModuleResolver},
+    timer::{self, Timers},
+    value::{Exception, JSValueRef},
+    worker::{self, Workers},
+};
+use log::{error, warn};
 use quickjs_sys as sys;
 use std::{
-    ffi::{c_char, c_void, CStr},
-    fs,
-    mem::ManuallyDrop,
-    path::Path,
+    borrow::Cow,
+    cell::RefCell,
+    collections::HashMap,
+    ffi::{c_char, c_void, CStr, CString},
+    io,
+    mem::{ManuallyDrop, MaybeUninit},
+    path::PathBuf,
     ptr::null_mut,
+    thread,
+    time::Duration,
 };
 
-pub trait UserLoader {
-    fn load(
-        &self,
-        ctx: *mut sys::JSContext,
-        module_name: *const c_char,
-    ) -> Option<*mut sys::JSModuleDef>;
+pub(crate) struct State {
+    pub(crate) cache: RefCell<Option<BytecodeCache>>,
+    pub(crate) import_map: RefCell<Option<ImportMap>>,
+    pub(crate) resolver: RefCell<Box<dyn ModuleResolver>>,
+    pub(crate) loader: RefCell<Box<dyn ModuleLoader>>,
+    pub(crate) native: RefCell<Registry>,
+    pub(crate) import_meta: RefCell<Option<Box<ImportMetaHook>>>,
+    pub(crate) timers: RefCell<Timers>,
+    pub(crate) workers: RefCell<Workers>,
+    pub(crate) helpers: RefCell<HashMap<(*mut sys::JSContext, &'static str), JSValueRef>>,
+    #[cfg(feature = "libc")]
+    pub(crate) libc: std::cell::Cell<bool>,
+    pub(crate) allocator: Option<Box<Accounting>>,
+}
+
+impl State {
+    /// # Safety
+    /// `ctx` must have been created through [`Context::from`] or a
+    /// [`ContextBuilder`](crate::context::ContextBuilder).
+    pub(crate) unsafe fn from_context<'a>(ctx: *mut sys::JSContext) -> &'a State {
+        &*(sys::JS_GetContextOpaque(ctx) as *const State)
+    }
+}
+
+impl Default for State {
+    fn default() -> Self {
+        State {
+            cache: RefCell::new(None),
+            import_map: RefCell::new(None),
+            resolver: RefCell::new(Box::<FileResolver>::default()),
+            loader: RefCell::new(Box::new(FileLoader)),
+            native: RefCell::new(Registry::default()),
+            import_meta: RefCell::new(None),
+            timers: RefCell::new(Timers::default()),
+            workers: RefCell::new(Workers::default()),
+            helpers: RefCell::new(HashMap::new()),
+            #[cfg(feature = "libc")]
+            libc: std::cell::Cell::new(false),
+            allocator: None,
+        }
+    }
+}
+
+fn throw(ctx: *mut sys::JSContext, message: impl AsRef<str>) {
+    if let Ok(message) = CString::new(message.as_ref()) {
+        unsafe {
+            sys::JS_ThrowReferenceError(ctx, b"%s\0".as_ptr() as *const c_char, message.as_ptr());
+        }
+    }
+}
+
+fn throw_type_error(ctx: *mut sys::JSContext, message: impl AsRef<str>) {
+    if let Ok(message) = CString::new(message.as_ref()) {
+        unsafe {
+            sys::JS_ThrowTypeError(ctx, b"%s\0".as_ptr() as *const c_char, message.as_ptr());
+        }
+    }
+}
+
+fn compile_module(ctx: &Context, module: &str, source: &str) -> Result<JSValueRef, QuickError> {
+    let state = unsafe { State::from_context(ctx.0) };
+
+    let cache = state.cache.borrow();
+    let cache = match cache.as_ref() {
+        Some(v) => v,
+        None => return ctx.eval_module(source, module),
+    };
+
+    if let Some(bytecode) = cache.get(module, source) {
+        match ctx.read_bytecode(&bytecode) {
+            Ok(value) => return Ok(value),
+            Err(e) => warn!("{module}: {e}"),
+        }
+    }
+
+    let value = ctx.eval_module(source, module)?;
+
+    match ctx.write_bytecode(&value) {
+        Ok(bytecode) => {
+            if let Err(e) = cache.put(module, source, &bytecode) {
+                warn!("{module}: {e}");
+            }
+        }
+        Err(e) => warn!("{module}: {e}"),
+    }
+
+    Ok(value)
+}
+
+fn module_value(
+    ctx: &Context,
+    module: &str,
+    source: ModuleSource,
+) -> Result<JSValueRef, QuickError> {
+    let value = match source {
+        ModuleSource::Source(source) => compile_module(ctx, module, &source)?,
+        ModuleSource::Bytecode(bytecode) => ctx.read_bytecode(&bytecode)?,
+        ModuleSource::Native(_) => {
+            return Err(QuickError::ModuleError(format!("{module}: native module")))
+        }
+    };
+
+    if value.tag() != sys::JS_TAG_MODULE {
+        return Err(QuickError::ModuleError(format!("{module}: not a module")));
+    }
+
+    Ok(value)
+}
+
+/// Resolves and compiles `name` through the runtime's resolver and loader
+/// without evaluating it, for use as an entry module.
+pub(crate) fn load_module(ctx: &Context, name: &str) -> Result<JSValueRef, QuickError> {
+    let state = unsafe { State::from_context(ctx.0) };
+
+    let module = state.resolver.borrow().resolve(ctx, "", name)?;
+    let source = state.loader.borrow().load(ctx, &module)?;
+
+    module_value(ctx, &module, source)
+}
+
+fn define_module(
+    ctx: &Context,
+    module: &str,
+    source: ModuleSource,
+) -> Result<*mut sys::JSModuleDef, QuickError> {
+    let value = match source {
+        ModuleSource::Native(module) => return Ok(module.as_ptr()),
+        source => module_value(ctx, module, source)?,
+    };
+
+    let def = value.ptr() as *mut sys::JSModuleDef;
+    module::init_import_meta(ctx, def, module, false)?;
+
+    Ok(def)
 }
 
 extern "C" fn module_normalize(
     ctx: *mut sys::JSContext,
-    _module_base_name: *const c_char,
+    module_base_name: *const c_char,
     module_name: *const c_char,
     _opaque: *mut c_void,
 ) -> *mut c_char {
-    unsafe { sys::js_strdup(ctx, module_name) }
+    let (base, name) = unsafe {
+        (
+            CStr::from_ptr(module_base_name).to_string_lossy(),
+            CStr::from_ptr(module_name).to_string_lossy(),
+        )
+    };
+
+    let state = unsafe { State::from_context(ctx) };
+
+    let mapped = match state.import_map.borrow().as_ref() {
+        Some(map) => map.resolve(&base, &name),
+        None => Ok(None),
+    };
+    let mapped = match mapped {
+        Ok(v) => v,
+        Err(e) => {
+            throw_type_error(ctx, e.to_string());
+            return null_mut();
+        }
+    };
+    let name = mapped.map(Cow::Owned).unwrap_or(name);
+
+    if state.native.borrow().modules.contains_key(name.as_ref()) {
+        return match CString::new(name.as_ref()) {
+            Ok(v) => unsafe { sys::js_strdup(ctx, v.as_ptr()) },
+            Err(e) => {
+                throw(ctx, e.to_string());
+                null_mut()
+            }
+        };
+    }
+
+    let context = ManuallyDrop::new(Context(ctx));
+
+    let resolved = state.resolver.borrow().resolve(&context, &base, &name);
+    match resolved
+        .and_then(|v| CString::new(v).map_err(|e| QuickError::CStringError(e.to_string())))
+    {
+        Ok(v) => unsafe { sys::js_strdup(ctx, v.as_ptr()) },
+        Err(e) => {
+            throw(ctx, e.to_string());
+            null_mut()
+        }
+    }
 }
 
 extern "C" fn module_loader(
     ctx: *mut sys::JSContext,
     module: *const c_char,
-    opaque: *mut c_void,
+    _opaque: *mut c_void,
 ) -> *mut sys::JSModuleDef {
-    if !opaque.is_null() {
-        let loader = unsafe { Box::from_raw(opaque as *mut &mut dyn UserLoader) };
-        let loader = ManuallyDrop::new(loader);
-
-        if let Some(module) = loader.load(ctx, module) {
-            return module;
-        }
-    }
-
     let module = unsafe { CStr::from_ptr(module) }
         .to_string_lossy()
         .to_string();
 
-    let source = if Path::new(&module).exists() {
-        fs::read_to_string(&module).ok()
-    } else {
-        None
+    let state = unsafe { State::from_context(ctx) };
+    let context = ManuallyDrop::new(Context(ctx));
+
+    let native = state.native.borrow().modules.get(&module).cloned();
+    let source = match native {
+        Some(builder) => builder.build(&context, &module).map(ModuleSource::Native),
+        None => state.loader.borrow().load(&context, &module),
     };
+    match source.and_then(|v| define_module(&context, &module, v)) {
+        Ok(v) => v,
+        Err(e) => {
+            error!("{e}");
+            throw(ctx, e.to_string());
+            null_mut()
+        }
+    }
+}
 
-    if let Some(source) = source {
-        let ctx = ManuallyDrop::new(Context(ctx));
+pub(crate) fn execute_pending_job(rt: *mut sys::JSRuntime) -> Result<bool, QuickError> {
+    let mut ctx = null_mut();
 
-        return match ctx.eval_module(source.as_str(), module.as_str()) {
-            Ok(value) => value.ptr() as *mut sys::JSModuleDef,
-            Err(e) => {
-                error!("{e}");
-                null_mut()
-            }
-        };
+    match unsafe { sys::JS_ExecutePendingJob(rt, &mut ctx) } {
+        0 => Ok(false),
+        n if n > 0 => Ok(true),
+        _ => {
+            let value = unsafe { sys::JS_GetException(ctx) };
+            let value = JSValueRef::from_value(ctx, value);
+
+            Err(Exception(value).into_error(QuickError::JobError))
+        }
     }
+}
 
-    null_mut()
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RuntimeBuilder {
+    memory_limit: Option<usize>,
+    max_stack_size: Option<usize>,
+    gc_threshold: Option<usize>,
 }
 
-pub struct Runtime(pub *mut sys::JSRuntime);
+impl RuntimeBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn memory_limit(mut self, limit: usize) -> Self {
+        self.memory_limit = Some(limit);
+        self
+    }
+
+    pub fn max_stack_size(mut self, size: usize) -> Self {
+        self.max_stack_size = Some(size);
+        self
+    }
+
+    pub fn gc_threshold(mut self, threshold: usize) -> Self {
+        self.gc_threshold = Some(threshold);
+        self
+    }
+
+    pub fn build(&self) -> Result<Runtime, QuickError> {
+        let rt = unsafe { sys::JS_NewRuntime() };
+        if rt.is_null() {
+            return Err(QuickError::OutOfMemory);
+        }
+
+        let runtime = unsafe { Runtime::from_raw(rt, State::default()) };
+        self.apply(&runtime);
+
+        Ok(runtime)
+    }
+
+    pub(crate) fn apply(&self, runtime: &Runtime) {
+        if let Some(limit) = self.memory_limit {
+            runtime.set_memory_limit(Some(limit));
+        }
+
+        if let Some(size) = self.max_stack_size {
+            runtime.set_max_stack_size(Some(size));
+        }
+
+        if let Some(threshold) = self.gc_threshold {
+            runtime.set_gc_threshold(threshold);
+        }
+    }
+}
+
+pub struct Runtime(pub *mut sys::JSRuntime, pub(crate) *mut State);
 
 impl Runtime {
-    pub fn new(heap: usize, stack: usize, loader: Option<Box<&mut dyn UserLoader>>) -> Self {
+    pub fn new(heap: usize, stack: usize) -> Self {
+        let mut builder = RuntimeBuilder::new();
+
+        if heap != 0 {
+            builder = builder.memory_limit(heap);
+        }
+
+        if stack != 0 {
+            builder = builder.max_stack_size(stack);
+        }
+
+        builder.build().expect("JS_NewRuntime")
+    }
+
+    pub fn builder() -> RuntimeBuilder {
+        RuntimeBuilder::new()
+    }
+
+    /// Creates a runtime whose memory is served by `allocator`, tracked
+    /// separately from every other runtime in the process.
+    pub fn with_allocator(allocator: impl JsAllocator + 'static) -> Result<Self, QuickError> {
+        let accounting = Box::new(Accounting::new(Box::new(allocator)));
+
         let rt = unsafe {
-            let rt = sys::JS_NewRuntime();
+            sys::JS_NewRuntime2(
+                &allocator::FUNCTIONS,
+                &*accounting as *const Accounting as *mut c_void,
+            )
+        };
+        if rt.is_null() {
+            return Err(QuickError::OutOfMemory);
+        }
 
-            if heap != 0 {
-                sys::JS_SetMemoryLimit(rt, heap);
-            }
+        let state = State {
+            allocator: Some(accounting),
+            ..State::default()
+        };
+
+        Ok(unsafe { Self::from_raw(rt, state) })
+    }
+
+    /// # Safety
+    /// `rt` must be a freshly created runtime; ownership passes to the result.
+    pub(crate) unsafe fn from_raw(rt: *mut sys::JSRuntime, state: State) -> Self {
+        sys::JS_SetModuleLoaderFunc(rt, Some(module_normalize), Some(module_loader), null_mut());
+
+        let state = Box::into_raw(Box::new(state));
+        Self(rt, state)
+    }
+
+    /// Returns `None` unless the runtime was created with [`Runtime::with_allocator`].
+    pub fn allocator_stats(&self) -> Option<AllocatorStats> {
+        self.state().allocator.as_ref().map(|v| v.stats())
+    }
+
+    /// Hard cap on the bytes requested from the custom allocator, including
+    /// per-allocation headers. Has no effect without [`Runtime::with_allocator`].
+    pub fn set_allocator_quota(&self, quota: Option<usize>) {
+        if let Some(allocator) = &self.state().allocator {
+            allocator.set_quota(quota);
+        }
+    }
+
+    pub fn set_memory_limit(&self, limit: Option<usize>) {
+        unsafe { sys::JS_SetMemoryLimit(self.0, limit.unwrap_or(0)) };
+    }
+
+    pub fn set_max_stack_size(&self, size: Option<usize>) {
+        unsafe { sys::JS_SetMaxStackSize(self.0, size.unwrap_or(0)) };
+    }
+
+    pub fn set_gc_threshold(&self, threshold: usize) {
+        unsafe { sys::JS_SetGCThreshold(self.0, threshold) };
+    }
+
+    pub(crate) fn state(&self) -> &State {
+        unsafe { &*self.1 }
+    }
+
+    pub fn set_resolver(&self, resolver: Box<dyn ModuleResolver>) {
+        *self.state().resolver.borrow_mut() = resolver;
+    }
 
-            if stack != 0 {
-                sys::JS_SetMaxStackSize(rt, stack);
+    pub fn set_loader(&self, loader: Box<dyn ModuleLoader>) {
+        *self.state().loader.borrow_mut() = loader;
+    }
+
+    pub fn set_import_map(&self, map: Option<ImportMap>) {
+        *self.state().import_map.borrow_mut() = map;
+    }
+
+    pub fn set_import_meta_hook<F>(&self, hook: F)
+    where
+        F: Fn(&Context, &str, &JSValueRef) -> Result<(), QuickError> + 'static,
+    {
+        *self.state().import_meta.borrow_mut() = Some(Box::new(hook));
+    }
+
+    pub fn register_module(&self, name: impl Into<String>, builder: ModuleBuilder) {
+        self.state()
+            .native
+            .borrow_mut()
+            .modules
+            .insert(name.into(), builder);
+    }
+
+    pub fn set_cache_dir(&self, dir: impl Into<PathBuf>) -> io::Result<()> {
+        let cache = BytecodeCache::new(dir)?;
+        *self.state().cache.borrow_mut() = Some(cache);
+
+        Ok(())
+    }
+
+    pub fn clear_cache_dir(&self) {
+        *self.state().cache.borrow_mut() = None;
+    }
+
+    pub fn is_job_pending(&self) -> bool {
+        unsafe { sys::JS_IsJobPending(self.0) != 0 }
+    }
+
+    pub fn execute_pending_job(&self) -> Result<bool, QuickError> {
+        execute_pending_job(self.0)
+    }
+
+    pub fn run_pending_jobs(&self) -> Result<usize, QuickError> {
+        let mut count = 0;
+        while execute_pending_job(self.0)? {
+            count += 1;
+        }
+
+        Ok(count)
+    }
+
+    pub fn set_virtual_clock(&self, enabled: bool) {
+        self.state().timers.borrow_mut().set_virtual(enabled);
+    }
+
+    pub fn advance_clock(&self, duration: Duration) -> Result<usize, QuickError> {
+        self.state().timers.borrow_mut().advance(duration);
+
+        timer::run_due(self)
+    }
+
+    pub fn has_pending_timers(&self) -> bool {
+        !self.state().timers.borrow().is_empty()
+    }
+
+    pub fn run_event_loop(&self) -> Result<(), QuickError> {
+        let state = self.state();
+
+        loop {
+            self.run_pending_jobs()?;
+            timer::run_due(self)?;
+            worker::run_due(self)?;
+
+            if self.is_job_pending() {
+                continue;
             }
 
-            let opaque = match loader {
-                Some(loader) => Box::into_raw(loader) as _,
-                None => null_mut(),
+            let (deadline, now, virtual_clock) = {
+                let mut timers = state.timers.borrow_mut();
+                (timers.next_deadline(), timers.now(), timers.is_virtual())
             };
-            sys::JS_SetModuleLoaderFunc(rt, Some(module_normalize), Some(module_loader), opaque);
+            let workers = !state.workers.borrow().is_empty();
 
-            rt
-        };
+            match deadline {
+                None if workers => state.workers.borrow_mut().wait(None),
+                None => return Ok(()),
+                Some(deadline) if virtual_clock => state
+                    .timers
+                    .borrow_mut()
+                    .advance(deadline.saturating_sub(now)),
+                Some(deadline) if workers => state
+                    .workers
+                    .borrow_mut()
+                    .wait(Some(deadline.saturating_sub(now))),
+                Some(deadline) => thread::sleep(deadline.saturating_sub(now)),
+            }
+        }
+    }
 
-        Self(rt)
+    #[cfg(feature = "libc")]
+    pub fn enable_libc(&self) {
+        if !self.state().libc.replace(true) {
+            unsafe { sys::js_std_init_handlers(self.0) };
+        }
+    }
+
+    pub fn memory_usage(&self) -> MemoryUsage {
+        let mut usage = MaybeUninit::<sys::JSMemoryUsage>::uninit();
+
+        unsafe {
+            sys::JS_ComputeMemoryUsage(self.0, usage.as_mut_ptr());
+            usage.assume_init().into()
+        }
     }
 
     pub fn gc(&self) {
@@ -101,14 +522,25 @@ impl Runtime {
 
 impl Default for Runtime {
     fn default() -> Self {
-        Self::new(0, 0, None)
+        Self::new(0, 0)
     }
 }
 
 impl Drop for Runtime {
     fn drop(&mut self) {
         unsafe {
+            (*self.1).workers.borrow_mut().clear();
+            (*self.1).helpers.borrow_mut().clear();
+            (*self.1).timers.borrow_mut().clear();
+
+            #[cfg(feature = "libc")]
+            if (*self.1).libc.get() {
+                sys::js_std_free_handlers(self.0);
+            }
+
             sys::JS_FreeRuntime(self.0);
+
+            drop(Box::from_raw(self.1));
         }
     }
 }
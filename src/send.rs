@@ -0,0 +1,66 @@
+use crate::{
+    context::{Context, ContextBuilder},
+    error::QuickError,
+    runtime::{Runtime, RuntimeBuilder},
+};
+use quickjs_sys as sys;
+
+/// A runtime moved between threads together with all of its contexts.
+///
+/// Values never leave [`SendRuntime::with`]: the closure and its result must
+/// be `Send`, and `JSValueRef`, `Context` and `Runtime` are not, so nothing
+/// tied to the runtime can be captured, returned or left behind on the old
+/// thread.
+pub struct SendRuntime {
+    contexts: Vec<Context>,
+    runtime: Runtime,
+}
+
+// Safety: the runtime and every context created on it are owned here and
+// only reachable through `with`, which cannot leak them to another thread.
+unsafe impl Send for SendRuntime {}
+
+impl SendRuntime {
+    pub fn new(builder: &RuntimeBuilder) -> Result<Self, QuickError> {
+        Ok(unsafe { Self::from_runtime(builder.build()?) })
+    }
+
+    /// # Safety
+    /// No context or value may have been created on `runtime` yet; they would
+    /// stay behind when the runtime moves to another thread.
+    pub unsafe fn from_runtime(runtime: Runtime) -> Self {
+        SendRuntime {
+            contexts: Vec::new(),
+            runtime,
+        }
+    }
+
+    pub fn add_context(&mut self, builder: &ContextBuilder) -> Result<usize, QuickError> {
+        self.update_stack_top();
+
+        let context = builder.build(&self.runtime)?;
+        self.contexts.push(context);
+
+        Ok(self.contexts.len() - 1)
+    }
+
+    pub fn with<F, R>(&mut self, f: F) -> R
+    where
+        F: FnOnce(&Runtime, &[Context]) -> R + Send,
+        R: Send,
+    {
+        self.update_stack_top();
+        f(&self.runtime, &self.contexts)
+    }
+
+    /// Gives the runtime back to the current thread. The contexts come first
+    /// so dropping the tuple frees them before the runtime.
+    pub fn into_inner(mut self) -> (Vec<Context>, Runtime) {
+        self.update_stack_top();
+        (self.contexts, self.runtime)
+    }
+
+    fn update_stack_top(&mut self) {
+        unsafe { sys::JS_UpdateStackTop(self.runtime.0) };
+    }
+}
@@ -0,0 +1,121 @@
+use crate::{
+    context::{Context, ContextBuilder},
+    error::QuickError,
+    module::Module,
+    runtime::Runtime,
+    value::JSValueRef,
+};
+use quickjs_sys as sys;
+use std::sync::Arc;
+
+const WRITE_FLAGS: i32 = sys::JS_WRITE_OBJ_REFERENCE as i32;
+const READ_FLAGS: i32 = sys::JS_READ_OBJ_REFERENCE as i32;
+
+type Setup = dyn Fn(&Context) -> Result<(), QuickError> + Send + Sync;
+
+#[derive(Clone)]
+enum Step {
+    Bytecode(Vec<u8>),
+    Global(String, Vec<u8>),
+    Setup(Arc<Setup>),
+}
+
+/// Work done once and replayed on every context spawned from the template:
+/// preludes are kept as bytecode and data globals as serialized objects, so
+/// nothing is parsed again.
+#[derive(Clone)]
+pub struct ContextTemplate {
+    builder: ContextBuilder,
+    steps: Vec<Step>,
+}
+
+impl ContextTemplate {
+    pub fn new(builder: ContextBuilder) -> Self {
+        ContextTemplate {
+            builder,
+            steps: Vec::new(),
+        }
+    }
+
+    pub fn script(
+        self,
+        source: impl AsRef<str>,
+        name: impl AsRef<str>,
+    ) -> Result<Self, QuickError> {
+        let runtime = Runtime::default();
+        let context = Context::from(&runtime);
+        let bytecode = context.compile_to_bytecode(source, name)?;
+        drop(context);
+
+        Ok(self.bytecode(bytecode))
+    }
+
+    pub fn bytecode(mut self, bytecode: impl Into<Vec<u8>>) -> Self {
+        self.steps.push(Step::Bytecode(bytecode.into()));
+        self
+    }
+
+    /// Serializes `value` now and defines it as the global `name` in every
+    /// spawned context. Shared references and cycles are preserved; functions
+    /// are not serializable.
+    pub fn global(
+        mut self,
+        ctx: &Context,
+        name: impl Into<String>,
+        value: &JSValueRef,
+    ) -> Result<Self, QuickError> {
+        let data = ctx.write_object(value, WRITE_FLAGS)?;
+        self.steps.push(Step::Global(name.into(), data));
+
+        Ok(self)
+    }
+
+    /// Captures the named globals of an already prepared context.
+    pub fn snapshot(self, ctx: &Context, names: &[&str]) -> Result<Self, QuickError> {
+        let global = ctx.global();
+
+        names.iter().try_fold(self, |template, name| {
+            template.global(ctx, *name, &global.property(name)?)
+        })
+    }
+
+    /// Registers native functions and other state that cannot be serialized.
+    pub fn setup<F>(mut self, setup: F) -> Self
+    where
+        F: Fn(&Context) -> Result<(), QuickError> + Send + Sync + 'static,
+    {
+        self.steps.push(Step::Setup(Arc::new(setup)));
+        self
+    }
+
+    pub fn instantiate(&self, runtime: &Runtime) -> Result<Context, QuickError> {
+        let context = self.builder.build(runtime)?;
+
+        let global = context.global();
+
+        for step in &self.steps {
+            match step {
+                Step::Bytecode(bytecode) => {
+                    let value = context.load_bytecode(bytecode)?;
+                    if value.tag() == sys::JS_TAG_MODULE {
+                        Module::new(value, false)?;
+                    }
+                }
+                Step::Global(name, data) => {
+                    global.set_property(name, context.read_object(data, READ_FLAGS)?)?;
+                }
+                Step::Setup(setup) => setup(&context)?,
+            }
+        }
+
+        drop(global);
+
+        Ok(context)
+    }
+}
+
+impl Default for ContextTemplate {
+    fn default() -> Self {
+        Self::new(ContextBuilder::new())
+    }
+}
@@ -0,0 +1,343 @@
+use crate::{
+    context::Context,
+    error::QuickError,
+    function,
+    runtime::{Runtime, State},
+    value::JSValueRef,
+};
+use quickjs_sys as sys;
+use std::{
+    collections::{BTreeSet, HashMap},
+    ffi::c_int,
+    mem::ManuallyDrop,
+    ptr,
+    time::{Duration, Instant},
+};
+
+struct Timer {
+    callback: JSValueRef,
+    args: Vec<JSValueRef>,
+    interval: Option<Duration>,
+}
+
+pub(crate) struct Timers {
+    start: Instant,
+    clock: Option<Duration>,
+    next_id: i32,
+    queue: BTreeSet<(Duration, i32)>,
+    timers: HashMap<i32, Timer>,
+}
+
+impl Timers {
+    pub(crate) fn now(&self) -> Duration {
+        match self.clock {
+            Some(v) => v,
+            None => self.start.elapsed(),
+        }
+    }
+
+    pub(crate) fn set_virtual(&mut self, enabled: bool) {
+        self.clock = if enabled { Some(self.now()) } else { None };
+        if !enabled {
+            self.start = Instant::now();
+        }
+    }
+
+    pub(crate) fn is_virtual(&self) -> bool {
+        self.clock.is_some()
+    }
+
+    pub(crate) fn advance(&mut self, duration: Duration) {
+        if let Some(clock) = &mut self.clock {
+            *clock += duration;
+        }
+    }
+
+    pub(crate) fn next_deadline(&mut self) -> Option<Duration> {
+        while let Some(&(deadline, id)) = self.queue.first() {
+            if self.timers.contains_key(&id) {
+                return Some(deadline);
+            }
+            self.queue.pop_first();
+        }
+
+        None
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.timers.is_empty()
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.queue.clear();
+        self.timers.clear();
+    }
+
+    /// Drops the timers created in `ctx`; their queue entries are skipped.
+    pub(crate) fn remove_context(&mut self, ctx: *mut sys::JSContext) {
+        self.timers.retain(|_, timer| timer.callback.ctx != ctx);
+    }
+
+    fn insert(&mut self, timer: Timer, delay: Duration) -> i32 {
+        self.next_id = self.next_id.wrapping_add(1).max(1);
+        let id = self.next_id;
+
+        let deadline = self.now() + delay;
+        self.queue.insert((deadline, id));
+        self.timers.insert(id, timer);
+
+        id
+    }
+
+    fn remove(&mut self, id: i32) {
+        self.timers.remove(&id);
+    }
+
+    fn pop_due(&mut self) -> Option<(JSValueRef, Vec<JSValueRef>)> {
+        let deadline = self.next_deadline()?;
+        if deadline > self.now() {
+            return None;
+        }
+
+        let (_, id) = self.queue.pop_first()?;
+        let timer = self.timers.get(&id)?;
+
+        let fired = (timer.callback.clone(), timer.args.clone());
+        let interval = timer.interval;
+
+        match interval {
+            Some(interval) => {
+                let deadline = deadline.max(self.now()) + interval;
+                self.queue.insert((deadline, id));
+            }
+            None => {
+                self.timers.remove(&id);
+            }
+        }
+
+        Some(fired)
+    }
+}
+
+impl Default for Timers {
+    fn default() -> Self {
+        Timers {
+            start: Instant::now(),
+            clock: None,
+            next_id: 0,
+            queue: BTreeSet::new(),
+            timers: HashMap::new(),
+        }
+    }
+}
+
+pub(crate) fn run_due(runtime: &Runtime) -> Result<usize, QuickError> {
+    let state = runtime.state();
+    let mut count = 0;
+
+    loop {
+        let due = state.timers.borrow_mut().pop_due();
+        let (callback, args) = match due {
+            Some(v) => v,
+            None => break,
+        };
+
+        function::call(callback, None, args)?;
+        while runtime.execute_pending_job()? {}
+
+        count += 1;
+    }
+
+    Ok(count)
+}
+
+fn delay(ctx: &Context, value: Option<&ManuallyDrop<JSValueRef>>) -> Duration {
+    let mut delay = 0.0;
+
+    if let Some(value) = value {
+        if unsafe { sys::JS_ToFloat64(ctx.0, &mut delay, value.val) } < 0 {
+            let value = unsafe { sys::JS_GetException(ctx.0) };
+            drop(JSValueRef::from_value(ctx.0, value));
+        }
+    }
+
+    if delay.is_finite() && delay > 0.0 {
+        Duration::from_secs_f64(delay / 1000.0)
+    } else {
+        Duration::ZERO
+    }
+}
+
+unsafe extern "C" fn microtask(
+    ctx: *mut sys::JSContext,
+    _: c_int,
+    argv: *mut sys::JSValue,
+) -> sys::JSValue {
+    let undefined = ManuallyDrop::new(Context(ctx)).make_undefined().val();
+    sys::JS_Call(ctx, *argv, undefined, 0, ptr::null_mut())
+}
+
+pub(crate) fn install(ctx: &Context) -> Result<(), QuickError> {
+    let global = ctx.global();
+
+    for (name, repeat) in [("setTimeout", false), ("setInterval", true)] {
+        ctx.make_function(Some(global.clone()), name, 2, move |ctx, args| {
+            if !args[0].is_function() {
+                return ctx.throw_error(format!("{name}: callback is not a function"));
+            }
+
+            let delay = delay(&ctx, args.get(1));
+            let timer = Timer {
+                callback: (*args[0]).clone(),
+                args: args.iter().skip(2).map(|v| (**v).clone()).collect(),
+                interval: if repeat {
+                    Some(delay.max(Duration::from_millis(1)))
+                } else {
+                    None
+                },
+            };
+
+            let state = unsafe { State::from_context(ctx.0) };
+            let id = state.timers.borrow_mut().insert(timer, delay);
+
+            ctx.make_int(id)
+        });
+    }
+
+    for name in ["clearTimeout", "clearInterval"] {
+        ctx.make_function(Some(global.clone()), name, 1, |ctx, args| {
+            if let Ok(id) = args[0].to_i32() {
+                let state = unsafe { State::from_context(ctx.0) };
+                state.timers.borrow_mut().remove(id);
+            }
+
+            ctx.make_undefined()
+        });
+    }
+
+    ctx.make_function(Some(global), "queueMicrotask", 1, |ctx, args| {
+        if !args[0].is_function() {
+            return ctx.throw_error("queueMicrotask: callback is not a function");
+        }
+
+        let mut callback = args[0].val;
+        if unsafe { sys::JS_EnqueueJob(ctx.0, Some(microtask), 1, &mut callback) } < 0 {
+            return ctx.throw_error("queueMicrotask: failed to enqueue job");
+        }
+
+        ctx.make_undefined()
+    });
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn timer(ctx: &Context, id: i32, interval: Option<u64>) -> Timer {
+        Timer {
+            callback: ctx.make_int(id),
+            args: Vec::new(),
+            interval: interval.map(Duration::from_millis),
+        }
+    }
+
+    fn fired(timers: &mut Timers) -> Option<i32> {
+        timers
+            .pop_due()
+            .map(|(callback, _)| callback.to_i32().unwrap())
+    }
+
+    #[test]
+    fn fires_in_deadline_order() {
+        let runtime = Runtime::default();
+        let ctx = Context::from(&runtime);
+        let mut timers = Timers::default();
+        timers.set_virtual(true);
+
+        timers.insert(timer(&ctx, 1, None), Duration::from_millis(20));
+        timers.insert(timer(&ctx, 2, None), Duration::from_millis(10));
+        timers.insert(timer(&ctx, 3, None), Duration::from_millis(10));
+
+        assert_eq!(fired(&mut timers), None);
+
+        timers.advance(Duration::from_millis(20));
+        assert_eq!(fired(&mut timers), Some(2));
+        assert_eq!(fired(&mut timers), Some(3));
+        assert_eq!(fired(&mut timers), Some(1));
+        assert_eq!(fired(&mut timers), None);
+        assert!(timers.is_empty());
+    }
+
+    #[test]
+    fn skips_removed_timers() {
+        let runtime = Runtime::default();
+        let ctx = Context::from(&runtime);
+        let mut timers = Timers::default();
+        timers.set_virtual(true);
+        let start = timers.now();
+
+        let id = timers.insert(timer(&ctx, 1, None), Duration::from_millis(10));
+        timers.insert(timer(&ctx, 2, None), Duration::from_millis(30));
+        timers.remove(id);
+
+        assert_eq!(
+            timers.next_deadline(),
+            Some(start + Duration::from_millis(30))
+        );
+
+        timers.advance(Duration::from_millis(30));
+        assert_eq!(fired(&mut timers), Some(2));
+    }
+
+    #[test]
+    fn drops_timers_of_a_removed_context() {
+        let runtime = Runtime::default();
+        let first = Context::from(&runtime);
+        let second = Context::from(&runtime);
+        let mut timers = Timers::default();
+        timers.set_virtual(true);
+
+        timers.insert(timer(&first, 1, Some(10)), Duration::from_millis(10));
+        timers.insert(timer(&second, 2, None), Duration::from_millis(20));
+        timers.remove_context(first.0);
+
+        timers.advance(Duration::from_millis(20));
+        assert_eq!(fired(&mut timers), Some(2));
+        assert_eq!(fired(&mut timers), None);
+        assert!(timers.is_empty());
+    }
+
+    #[test]
+    fn reschedules_intervals() {
+        let runtime = Runtime::default();
+        let ctx = Context::from(&runtime);
+        let mut timers = Timers::default();
+        timers.set_virtual(true);
+        let start = timers.now();
+
+        let id = timers.insert(timer(&ctx, 1, Some(10)), Duration::from_millis(10));
+
+        timers.advance(Duration::from_millis(10));
+        assert_eq!(fired(&mut timers), Some(1));
+        assert_eq!(fired(&mut timers), None);
+        assert_eq!(
+            timers.next_deadline(),
+            Some(start + Duration::from_millis(20))
+        );
+
+        // A late tick is rescheduled from now instead of firing to catch up.
+        timers.advance(Duration::from_millis(35));
+        assert_eq!(fired(&mut timers), Some(1));
+        assert_eq!(fired(&mut timers), None);
+        assert_eq!(
+            timers.next_deadline(),
+            Some(start + Duration::from_millis(55))
+        );
+
+        timers.remove(id);
+        assert_eq!(timers.next_deadline(), None);
+        assert!(timers.is_empty());
+    }
+}
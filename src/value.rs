@@ -4,9 +4,9 @@ use log::error;
 use quickjs_sys as sys;
 use std::{
     f64,
-    ffi::{c_void, CString},
+    ffi::{c_void, CStr, CString},
     mem::{self, ManuallyDrop, MaybeUninit},
-    slice,
+    ptr, slice,
 };
 
 extern "C" {
@@ -68,6 +68,43 @@ impl JSValueRef {
         Ok(JSValueRef::from_value(self.ctx, value))
     }
 
+    pub fn keys(&self) -> Result<Vec<String>, QuickError> {
+        const FLAGS: i32 = (sys::JS_GPN_STRING_MASK | sys::JS_GPN_ENUM_ONLY) as i32;
+
+        let mut tab = ptr::null_mut();
+        let mut len = 0;
+
+        if unsafe { sys::JS_GetOwnPropertyNames(self.ctx, &mut tab, &mut len, self.val, FLAGS) } < 0
+        {
+            let value = unsafe { sys::JS_GetException(self.ctx) };
+            let value = JSValueRef::from_value(self.ctx, value);
+
+            return Err(Exception(value).into_error(QuickError::EvalError));
+        }
+
+        let mut keys = Vec::with_capacity(len as usize);
+
+        unsafe {
+            for entry in slice::from_raw_parts(tab, len as usize) {
+                let name = sys::JS_AtomToCString(self.ctx, entry.atom);
+                if !name.is_null() {
+                    keys.push(CStr::from_ptr(name).to_string_lossy().to_string());
+                    sys::JS_FreeCString(self.ctx, name);
+                }
+
+                sys::JS_FreeAtom(self.ctx, entry.atom);
+            }
+
+            sys::js_free(self.ctx, tab as *mut c_void);
+        }
+
+        Ok(keys)
+    }
+
+    pub fn is_function(&self) -> bool {
+        unsafe { sys::JS_IsFunction(self.ctx, self.val) != 0 }
+    }
+
     pub fn to_bool(&self) -> Result<bool, QuickError> {
         if self.tag == sys::JS_TAG_BOOL {
             Ok(unsafe { JS_VALUE_GET_INT_real(self.val) } != 0)
@@ -207,6 +244,23 @@ impl Drop for JSValueRef {
 
 pub struct Exception(pub JSValueRef);
 
+impl Exception {
+    pub fn is_out_of_memory(&self) -> bool {
+        let name = self.0.property("name").and_then(|v| v.to_string());
+        let message = self.0.property("message").and_then(|v| v.to_string());
+
+        matches!((name, message), (Ok(name), Ok(message)) if name == "InternalError" && message == "out of memory")
+    }
+
+    pub fn into_error(self, error: fn(String) -> QuickError) -> QuickError {
+        if self.is_out_of_memory() {
+            QuickError::OutOfMemory
+        } else {
+            error(self.to_string())
+        }
+    }
+}
+
 impl ToString for Exception {
     fn to_string(&self) -> String {
         let name = match self.0.property("name").and_then(|v| v.to_string()) {
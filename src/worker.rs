@@ -0,0 +1,458 @@
+use crate::{
+    clone,
+    context::Context,
+    error::QuickError,
+    function,
+    module::Module,
+    runtime::{self, Runtime, State},
+    timer,
+    value::JSValueRef,
+};
+use log::error;
+use quickjs_sys as sys;
+use std::{
+    cell::Cell,
+    collections::{HashMap, VecDeque},
+    ffi::{c_int, c_void},
+    mem::ManuallyDrop,
+    rc::Rc,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, RecvTimeoutError},
+        Arc, Weak,
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+const PRELUDE: &str = r#"
+(function (spawn, post, terminate) {
+    return class Worker {
+        #id;
+
+        constructor(name) {
+            this.onmessage = null;
+            this.onerror = null;
+            this.#id = spawn(String(name), this);
+        }
+
+        postMessage(message, transfer = []) {
+            post(this.#id, message, transfer);
+        }
+
+        terminate() {
+            terminate(this.#id);
+        }
+    };
+})
+"#;
+
+pub enum WorkerEvent {
+    /// A message serialized with [`clone::serialize`].
+    Message(Vec<u8>),
+    Error(String),
+    Exit,
+}
+
+enum Command {
+    Message(Vec<u8>),
+    Terminate,
+}
+
+type Outbox = dyn Fn(WorkerEvent) + Send + Sync;
+
+/// A module running on its own runtime and thread. The worker resolves the
+/// module with the default file loader and resolver.
+pub struct Worker {
+    commands: mpsc::Sender<Command>,
+    events: Option<mpsc::Receiver<WorkerEvent>>,
+    terminated: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Worker {
+    pub fn spawn(module: impl Into<String>) -> Result<Self, QuickError> {
+        let (sender, receiver) = mpsc::channel();
+
+        let mut worker = Self::start(
+            module.into(),
+            Arc::new(move |event| {
+                let _ = sender.send(event);
+            }),
+        )?;
+        worker.events = Some(receiver);
+
+        Ok(worker)
+    }
+
+    fn start(module: String, outbox: Arc<Outbox>) -> Result<Self, QuickError> {
+        let (commands, receiver) = mpsc::channel();
+        let terminated = Arc::new(AtomicBool::new(false));
+
+        let terminated_ = terminated.clone();
+        let thread = thread::Builder::new()
+            .name(format!("quickjs-worker {module}"))
+            .spawn(move || run(&module, &receiver, outbox, &terminated_))
+            .map_err(|e| QuickError::WorkerError(e.to_string()))?;
+
+        Ok(Worker {
+            commands,
+            events: None,
+            terminated,
+            thread: Some(thread),
+        })
+    }
+
+    pub fn post_message(&self, value: &JSValueRef) -> Result<(), QuickError> {
+        self.post_bytes(clone::serialize(value, &[])?)
+    }
+
+    pub fn post_bytes(&self, bytes: Vec<u8>) -> Result<(), QuickError> {
+        self.commands
+            .send(Command::Message(bytes))
+            .map_err(|_| QuickError::WorkerError(String::from("worker has exited")))
+    }
+
+    /// Blocks until the worker sends an event; returns `None` after
+    /// [`WorkerEvent::Exit`] has been received.
+    pub fn recv(&self) -> Option<WorkerEvent> {
+        self.events.as_ref()?.recv().ok()
+    }
+
+    pub fn try_recv(&self) -> Option<WorkerEvent> {
+        self.events.as_ref()?.try_recv().ok()
+    }
+
+    /// Interrupts any running script and stops the worker's event loop.
+    pub fn terminate(&self) {
+        self.terminated.store(true, Ordering::Relaxed);
+        let _ = self.commands.send(Command::Terminate);
+    }
+}
+
+impl Drop for Worker {
+    fn drop(&mut self) {
+        self.terminate();
+
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+unsafe extern "C" fn interrupt(_: *mut sys::JSRuntime, opaque: *mut c_void) -> c_int {
+    (*(opaque as *const AtomicBool)).load(Ordering::Relaxed) as c_int
+}
+
+fn run(
+    module: &str,
+    commands: &mpsc::Receiver<Command>,
+    outbox: Arc<Outbox>,
+    terminated: &Arc<AtomicBool>,
+) {
+    let runtime = Runtime::default();
+    unsafe {
+        sys::JS_SetInterruptHandler(
+            runtime.0,
+            Some(interrupt),
+            Arc::as_ptr(terminated) as *mut c_void,
+        );
+    }
+
+    let context = Context::from(&runtime);
+    let closed = Rc::new(Cell::new(false));
+
+    let report = |result: Result<(), QuickError>| {
+        if let Err(e) = result {
+            if !terminated.load(Ordering::Relaxed) {
+                outbox(WorkerEvent::Error(e.to_string()));
+            }
+        }
+    };
+
+    let started = context
+        .enable_console(None)
+        .and_then(|_| context.enable_timers())
+        .and_then(|_| install(&context, Arc::downgrade(&outbox), closed.clone()))
+        .and_then(|_| runtime::load_module(&context, module))
+        .and_then(|v| Module::new(v, true));
+
+    if started.is_ok() {
+        while !closed.get() && !terminated.load(Ordering::Relaxed) {
+            report(runtime.run_pending_jobs().map(|_| ()));
+            report(timer::run_due(&runtime).map(|_| ()));
+
+            let timeout = {
+                let mut timers = runtime.state().timers.borrow_mut();
+                let now = timers.now();
+                timers.next_deadline().map(|v| v.saturating_sub(now))
+            };
+
+            if runtime.is_job_pending() || closed.get() {
+                continue;
+            }
+
+            let command = match timeout {
+                Some(timeout) => match commands.recv_timeout(timeout) {
+                    Ok(v) => v,
+                    Err(RecvTimeoutError::Timeout) => continue,
+                    Err(RecvTimeoutError::Disconnected) => break,
+                },
+                None => match commands.recv() {
+                    Ok(v) => v,
+                    Err(_) => break,
+                },
+            };
+
+            match command {
+                Command::Message(bytes) => {
+                    let global = context.global();
+
+                    report(message(&global, &bytes).map(|_| ()));
+                }
+                Command::Terminate => break,
+            }
+        }
+    }
+    report(started.map(|_| ()));
+
+    drop(context);
+    drop(runtime);
+
+    outbox(WorkerEvent::Exit);
+}
+
+fn install(ctx: &Context, outbox: Weak<Outbox>, closed: Rc<Cell<bool>>) -> Result<(), QuickError> {
+    let global = ctx.global();
+
+    ctx.make_function(Some(global.clone()), "postMessage", 2, move |ctx, args| {
+        let bytes = transfer(args.get(1)).and_then(|v| clone::serialize(&args[0], &v));
+
+        match bytes {
+            Ok(bytes) => {
+                if let Some(outbox) = outbox.upgrade() {
+                    outbox(WorkerEvent::Message(bytes));
+                }
+                ctx.make_undefined()
+            }
+            Err(e) => ctx.throw_error(e.to_string()),
+        }
+    });
+
+    ctx.make_function(Some(global.clone()), "close", 0, move |ctx, _| {
+        closed.set(true);
+        ctx.make_undefined()
+    });
+
+    // Strict-mode modules assign `onmessage = ...` without `self.`, which
+    // throws unless the property already exists.
+    global.set_property("onmessage", ctx.make_null())?;
+    global.set_property("onerror", ctx.make_null())?;
+
+    global.set_property("self", global.clone())
+}
+
+fn transfer(value: Option<&ManuallyDrop<JSValueRef>>) -> Result<Vec<JSValueRef>, QuickError> {
+    match value {
+        Some(v) if v.tag() == sys::JS_TAG_OBJECT => v.to_array(),
+        _ => Ok(Vec::new()),
+    }
+}
+
+fn message(target: &JSValueRef, bytes: &[u8]) -> Result<bool, QuickError> {
+    dispatch(target, "onmessage", |ctx| {
+        let event = ctx.make_object();
+        event.set_property("data", clone::deserialize(ctx, bytes)?)?;
+
+        Ok(event)
+    })
+}
+
+fn dispatch<F>(target: &JSValueRef, name: &str, event: F) -> Result<bool, QuickError>
+where
+    F: FnOnce(&Context) -> Result<JSValueRef, QuickError>,
+{
+    let handler = target.property(name)?;
+    if !handler.is_function() {
+        return Ok(false);
+    }
+
+    let ctx = ManuallyDrop::new(Context(target.ctx));
+    let event = event(&ctx)?;
+
+    function::call(handler, Some(target.clone()), vec![event])?;
+
+    Ok(true)
+}
+
+pub(crate) struct Workers {
+    next_id: i32,
+    sender: mpsc::Sender<(i32, WorkerEvent)>,
+    receiver: mpsc::Receiver<(i32, WorkerEvent)>,
+    pending: VecDeque<(i32, WorkerEvent)>,
+    workers: HashMap<i32, (JSValueRef, Worker)>,
+}
+
+impl Workers {
+    pub(crate) fn is_empty(&self) -> bool {
+        self.workers.is_empty()
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.pending.clear();
+        self.workers.clear();
+    }
+
+    /// Terminates the workers spawned from `ctx`; their pending events are
+    /// skipped.
+    pub(crate) fn remove_context(&mut self, ctx: *mut sys::JSContext) {
+        self.workers.retain(|_, (handle, _)| handle.ctx != ctx);
+    }
+
+    /// Blocks until a worker event arrives or `timeout` elapses.
+    pub(crate) fn wait(&mut self, timeout: Option<Duration>) {
+        let event = match timeout {
+            Some(timeout) => self.receiver.recv_timeout(timeout).ok(),
+            None => self.receiver.recv().ok(),
+        };
+
+        self.pending.extend(event);
+    }
+
+    fn spawn(&mut self, module: String, handle: JSValueRef) -> Result<i32, QuickError> {
+        self.next_id = self.next_id.wrapping_add(1).max(1);
+        let id = self.next_id;
+
+        let sender = self.sender.clone();
+        let worker = Worker::start(
+            module,
+            Arc::new(move |event| {
+                let _ = sender.send((id, event));
+            }),
+        )?;
+        self.workers.insert(id, (handle, worker));
+
+        Ok(id)
+    }
+
+    fn remove(&mut self, id: i32) -> Option<(JSValueRef, Worker)> {
+        self.workers.remove(&id)
+    }
+
+    fn next(&mut self) -> Option<(i32, WorkerEvent)> {
+        self.pending
+            .pop_front()
+            .or_else(|| self.receiver.try_recv().ok())
+    }
+}
+
+impl Default for Workers {
+    fn default() -> Self {
+        let (sender, receiver) = mpsc::channel();
+
+        Workers {
+            next_id: 0,
+            sender,
+            receiver,
+            pending: VecDeque::new(),
+            workers: HashMap::new(),
+        }
+    }
+}
+
+pub(crate) fn run_due(runtime: &Runtime) -> Result<usize, QuickError> {
+    let state = runtime.state();
+    let mut count = 0;
+
+    loop {
+        let next = state.workers.borrow_mut().next();
+        let (id, event) = match next {
+            Some(v) => v,
+            None => break,
+        };
+
+        if let WorkerEvent::Exit = event {
+            let worker = state.workers.borrow_mut().remove(id);
+            drop(worker);
+            continue;
+        }
+
+        let handle = match state.workers.borrow().workers.get(&id) {
+            Some((handle, _)) => handle.clone(),
+            None => continue,
+        };
+
+        match event {
+            WorkerEvent::Message(bytes) => {
+                message(&handle, &bytes)?;
+            }
+            WorkerEvent::Error(message) => {
+                let handled = dispatch(&handle, "onerror", |ctx| {
+                    let event = ctx.make_object();
+                    event.set_property("message", ctx.make_string(&message)?)?;
+
+                    Ok(event)
+                })?;
+
+                if !handled {
+                    error!("worker: {message}");
+                }
+            }
+            WorkerEvent::Exit => {}
+        }
+
+        while runtime.execute_pending_job()? {}
+        count += 1;
+    }
+
+    Ok(count)
+}
+
+pub(crate) fn install_host(ctx: &Context) -> Result<(), QuickError> {
+    let spawn = ctx.new_function(2, |ctx, args| {
+        let module = match args[0].to_string() {
+            Ok(v) => v,
+            Err(e) => return ctx.throw_error(e.to_string()),
+        };
+
+        let state = unsafe { State::from_context(ctx.0) };
+        let id = state.workers.borrow_mut().spawn(module, (*args[1]).clone());
+
+        match id {
+            Ok(id) => ctx.make_int(id),
+            Err(e) => ctx.throw_error(e.to_string()),
+        }
+    });
+
+    let post = ctx.new_function(3, |ctx, args| {
+        let id = args[0].to_i32().unwrap_or_default();
+        let bytes = transfer(args.get(2)).and_then(|v| clone::serialize(&args[1], &v));
+
+        let state = unsafe { State::from_context(ctx.0) };
+        let sent = match (bytes, state.workers.borrow().workers.get(&id)) {
+            (Ok(bytes), Some((_, worker))) => worker.post_bytes(bytes),
+            (Ok(_), None) => Ok(()),
+            (Err(e), _) => Err(e),
+        };
+
+        match sent {
+            Ok(_) => ctx.make_undefined(),
+            Err(e) => ctx.throw_error(e.to_string()),
+        }
+    });
+
+    let terminate = ctx.new_function(1, |ctx, args| {
+        if let Ok(id) = args[0].to_i32() {
+            let state = unsafe { State::from_context(ctx.0) };
+            let worker = state.workers.borrow_mut().remove(id);
+            drop(worker);
+        }
+
+        ctx.make_undefined()
+    });
+
+    let prelude = ctx.eval_global(PRELUDE, "<worker>")?;
+    let class = function::call(prelude, None, vec![spawn, post, terminate])?;
+
+    ctx.global().set_property("Worker", class)
+}